@@ -3,6 +3,7 @@ use crate::*;
 pub struct Pipeline {
     pipeline: wgpu::RenderPipeline,
     skip: bool,
+    far_depth: f32,
 }
 
 const PIPELINE_NAME: &str = "Analytical SDF Cube";
@@ -14,14 +15,15 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
         let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
         };
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("analytical_sdf_cube.wgsl"));
+        let shader = include_shader!(device, "analytical_sdf_cube.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
             bind_group_layouts: &[&global_bind_group.bind_group_layout],
@@ -40,7 +42,7 @@ impl PipelineState for Pipeline {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -58,7 +60,7 @@ impl PipelineState for Pipeline {
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: wgpu::TextureFormat::Depth32Float,
                 depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+                depth_compare: projection.depth_compare(),
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
@@ -73,13 +75,14 @@ impl PipelineState for Pipeline {
         Self {
             pipeline,
             skip: false,
+            far_depth: projection.far_depth(),
         }
     }
 
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         clear_depth: bool,
     ) {
@@ -111,7 +114,7 @@ impl PipelineState for Pipeline {
                 view: &depth_attachment.view,
                 depth_ops: Some(wgpu::Operations {
                     load: if clear_depth {
-                        wgpu::LoadOp::Clear(1.0)
+                        wgpu::LoadOp::Clear(self.far_depth)
                     } else {
                         wgpu::LoadOp::Load
                     },
@@ -128,6 +131,10 @@ impl PipelineState for Pipeline {
         render_pass.draw(0..3, 0..1);
     }
 
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Scene
+    }
+
     fn get_skip(&self) -> bool {
         self.skip
     }
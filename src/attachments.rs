@@ -3,21 +3,185 @@ use crate::*;
 pub enum Attachment {
     Color(ColorAttachment),
     Depth(DepthAttachment),
+    GBuffer(GBufferAttachment),
+}
+
+/// (Re)creates every attachment that depends on the size of the surface.
+pub fn create_screen_attachments(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    attachments: &mut HashMap<String, Attachment>,
+    bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+) {
+    attachments.insert(
+        "depth".to_string(),
+        Attachment::Depth(DepthAttachment::create_depth_texture(
+            device,
+            config,
+            bind_groups,
+        )),
+    );
+    attachments.insert(
+        "gbuffer".to_string(),
+        Attachment::GBuffer(GBufferAttachment::create_gbuffer_textures(
+            device,
+            config,
+            bind_groups,
+        )),
+    );
+    // the scene is rendered offscreen so that it can be resolved and post processed
+    // before presenting, the bloom chain halves the resolution at every step
+    let bloom_names: Vec<String> = (0..BLOOM_MIP_COUNT).map(|i| format!("bloom_{i}")).collect();
+    let screen_textures = [
+        ("color", SCENE_COLOR_FORMAT, 0),
+        ("post", SCENE_COLOR_FORMAT, 0),
+        ("gi_history", GI_HISTORY_FORMAT, 0),
+        ("gi_history_prev", GI_HISTORY_FORMAT, 0),
+        ("taa_history", SCENE_COLOR_FORMAT, 0),
+        ("taa_history_prev", SCENE_COLOR_FORMAT, 0),
+    ]
+    .into_iter()
+    .chain(
+        bloom_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), SCENE_COLOR_FORMAT, i as u32 + 1)),
+    );
+    for (name, format, downscale) in screen_textures {
+        attachments.insert(
+            name.to_string(),
+            Attachment::Color(ColorAttachment::create_texture(
+                device,
+                (config.width >> downscale).max(1),
+                (config.height >> downscale).max(1),
+                format,
+                name,
+                bind_groups,
+            )),
+        );
+    }
 }
 
 pub struct ColorAttachment {
     pub view: wgpu::TextureView,
+    // missing for the surface, which is only a render target
+    pub texture: Option<wgpu::Texture>,
 }
 
 pub struct DepthAttachment {
     pub view: wgpu::TextureView,
 }
 
+pub const SCENE_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const GI_HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const BLOOM_MIP_COUNT: usize = 5;
+
+/// Color target of the pipelines that accumulate indirect light,
+/// the alpha channel stores the linear depth of the pixel.
+pub fn gi_history_target() -> Option<wgpu::ColorTargetState> {
+    Some(wgpu::ColorTargetState {
+        format: GI_HISTORY_FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+    })
+}
+
+impl ColorAttachment {
+    /// Creates a texture that is both a color attachment and
+    /// readable through the bind group with the same name.
+    pub fn create_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        name: &str,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> ColorAttachment {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(name),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&(name.to_string() + " Bind Group Layout")),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                count: None,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                visibility: wgpu::ShaderStages::FRAGMENT,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+            label: Some(&(name.to_string() + "_bind_group")),
+        });
+        bind_groups.insert(
+            name.to_string(),
+            Rc::new(BindGroupState {
+                buffer: vec![],
+                bind_group,
+                bind_group_layout,
+            }),
+        );
+        ColorAttachment {
+            view,
+            texture: Some(texture),
+        }
+    }
+}
+
+/// Swaps two attachments together with their bind groups,
+/// used to ping-pong between the current and the previous frame.
+pub fn swap_attachments(
+    attachments: &mut HashMap<String, Attachment>,
+    bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    a: &str,
+    b: &str,
+) {
+    let attachment_a = attachments.remove(a);
+    let attachment_b = attachments.remove(b);
+    if let Some(attachment) = attachment_b {
+        attachments.insert(a.to_string(), attachment);
+    }
+    if let Some(attachment) = attachment_a {
+        attachments.insert(b.to_string(), attachment);
+    }
+    let bind_group_a = bind_groups.remove(a);
+    let bind_group_b = bind_groups.remove(b);
+    if let Some(bind_group) = bind_group_b {
+        bind_groups.insert(a.to_string(), bind_group);
+    }
+    if let Some(bind_group) = bind_group_a {
+        bind_groups.insert(b.to_string(), bind_group);
+    }
+}
+
 impl DepthAttachment {
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> DepthAttachment {
         let size = wgpu::Extent3d {
             width: config.width.max(1),
@@ -85,12 +249,142 @@ impl DepthAttachment {
         });
         bind_groups.insert(
             "depth".to_string(),
-            BindGroupState {
+            Rc::new(BindGroupState {
                 buffer: vec![],
                 bind_group,
                 bind_group_layout,
-            },
+            }),
         );
         DepthAttachment { view }
     }
 }
+
+pub const GBUFFER_ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+pub const GBUFFER_NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const GBUFFER_BLOCK_ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+// the depth of the gbuffer is the shared depth attachment
+pub struct GBufferAttachment {
+    pub albedo_view: wgpu::TextureView,
+    pub normal_view: wgpu::TextureView,
+    pub block_id_view: wgpu::TextureView,
+}
+
+impl GBufferAttachment {
+    pub fn create_gbuffer_textures(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> GBufferAttachment {
+        let size = wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let create_view = |label: &str, format: wgpu::TextureFormat| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let albedo_view = create_view("GBuffer Albedo Texture", GBUFFER_ALBEDO_FORMAT);
+        let normal_view = create_view("GBuffer Normal Texture", GBUFFER_NORMAL_FORMAT);
+        let block_id_view = create_view("GBuffer Block Id Texture", GBUFFER_BLOCK_ID_FORMAT);
+
+        // the lighting pass reads texels with textureLoad, no sampler needed
+        let texture_entry =
+            |binding: u32, sample_type: wgpu::TextureSampleType| wgpu::BindGroupLayoutEntry {
+                binding,
+                count: None,
+                ty: wgpu::BindingType::Texture {
+                    sample_type,
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                visibility: wgpu::ShaderStages::FRAGMENT,
+            };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("GBuffer Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(2, wgpu::TextureSampleType::Uint),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&block_id_view),
+                },
+            ],
+            label: Some("gbuffer_bind_group"),
+        });
+        bind_groups.insert(
+            "gbuffer".to_string(),
+            Rc::new(BindGroupState {
+                buffer: vec![],
+                bind_group,
+                bind_group_layout,
+            }),
+        );
+        GBufferAttachment {
+            albedo_view,
+            normal_view,
+            block_id_view,
+        }
+    }
+
+    /// Color targets of a pipeline that writes to the gbuffer, in binding order.
+    pub fn targets() -> [Option<wgpu::ColorTargetState>; 3] {
+        [
+            GBUFFER_ALBEDO_FORMAT,
+            GBUFFER_NORMAL_FORMAT,
+            GBUFFER_BLOCK_ID_FORMAT,
+        ]
+        .map(|format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        })
+    }
+
+    pub fn color_attachments(
+        &self,
+        clear: bool,
+    ) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        [&self.albedo_view, &self.normal_view, &self.block_id_view].map(|view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        })
+    }
+}
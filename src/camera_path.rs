@@ -0,0 +1,209 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::*;
+
+pub const CAMERA_PATH_DIRECTORY: &str = "camera_paths";
+/// Plays this file instead of the latest recorded camera path.
+pub const CAMERA_PATH_ENV: &str = "SHADER_CAMERA_PATH";
+/// Seconds of simulation between the keyframes of a recording.
+const KEYFRAME_INTERVAL: f32 = 0.25;
+
+/// Where the camera was at a time since the start of the path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub pose: CameraPose,
+}
+
+/// Camera keyframes in the order of their time, stored one per line in the
+/// format of `--camera` after the time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// The position follows a catmull-rom spline through the keyframes and the
+    /// rotation turns along the shortest arc between them, None past the end.
+    pub fn sample(&self, time: f32) -> Option<(Vec3, Quat)> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        if time > self.duration() {
+            return None;
+        }
+        // the segment from the last keyframe at or before the time
+        let i = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            .saturating_sub(1)
+            .min(last.saturating_sub(1));
+        let (from, to) = (self.keyframes[i], self.keyframes[(i + 1).min(last)]);
+        let span = to.time - from.time;
+        let t = if span > 0.0 {
+            ((time - from.time) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        // the ends repeat their keyframe as the missing neighbour
+        let before = self.keyframes[i.saturating_sub(1)].pose.position;
+        let after = self.keyframes[(i + 2).min(last)].pose.position;
+        let position = catmull_rom(before, from.pose.position, to.pose.position, after, t);
+        let rotation = from.pose.rotation().slerp(to.pose.rotation(), t);
+        Some((position, rotation))
+    }
+
+    /// Keeps a keyframe every interval, the first one where the recording starts.
+    pub fn record(&mut self, time: f32, pose: CameraPose) {
+        let due = self
+            .keyframes
+            .last()
+            .is_none_or(|last| time - last.time >= KEYFRAME_INTERVAL - 1e-4);
+        if due {
+            self.keyframes.push(CameraKeyframe { time, pose });
+        }
+    }
+
+    /// Writes the path to a file named after the time.
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = PathBuf::from(CAMERA_PATH_DIRECTORY).join(format!("{}.csv", timestamp()));
+        std::fs::create_dir_all(CAMERA_PATH_DIRECTORY).map_err(|err| err.to_string())?;
+        let mut file = std::fs::File::create(&path).map_err(|err| err.to_string())?;
+        write!(file, "{self}").map_err(|err| err.to_string())?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())?
+            .parse()
+    }
+
+    /// The file of the environment variable, or else the last one recorded.
+    pub fn playback_file() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var(CAMERA_PATH_ENV) {
+            return Some(path.into());
+        }
+        // the timestamps of the names sort in the order they were recorded
+        std::fs::read_dir(CAMERA_PATH_DIRECTORY)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "csv"))
+            .max()
+    }
+}
+
+impl std::fmt::Display for CameraPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "time,x,y,z,yaw,pitch")?;
+        for keyframe in &self.keyframes {
+            writeln!(f, "{:.3},{}", keyframe.time, keyframe.pose)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for CameraPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keyframes: Vec<CameraKeyframe> = Vec::new();
+        // the first line is the header
+        for (number, line) in s.lines().enumerate().skip(1) {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |err: String| format!("line {}: {err}", number + 1);
+            let Some((time, pose)) = line.split_once(',') else {
+                return Err(error(format!("no time: {line}")));
+            };
+            let time: f32 = time
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("not a time: {time}")))?;
+            if keyframes.last().is_some_and(|last| last.time > time) {
+                return Err(error("the keyframes go back in time".to_string()));
+            }
+            let pose = pose.parse().map_err(error)?;
+            keyframes.push(CameraKeyframe { time, pose });
+        }
+        Ok(Self { keyframes })
+    }
+}
+
+/// The uniform spline through p1 and p2, t goes from one to the other.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// What the camera path does with the camera, it either samples the flying
+/// camera or moves it in place of the input.
+#[derive(Debug, Clone)]
+pub enum CameraPathState {
+    Recording { path: CameraPath, time: f32 },
+    Playing { path: CameraPath, time: f32 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, x: f32, yaw: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            pose: CameraPose {
+                position: Vec3::new(x, 1.0, 2.0),
+                yaw,
+                pitch: -10.0,
+            },
+        }
+    }
+
+    #[test]
+    fn sample_passes_through_the_keyframes() {
+        let path = CameraPath {
+            keyframes: vec![
+                keyframe(0.0, 0.0, 0.0),
+                keyframe(1.0, 4.0, 90.0),
+                keyframe(2.0, 6.0, 170.0),
+                keyframe(3.0, 6.0, -170.0),
+            ],
+        };
+        for keyframe in &path.keyframes {
+            let (position, rotation) = path.sample(keyframe.time).unwrap();
+            assert!(position.distance(keyframe.pose.position) < 1e-5);
+            assert!(rotation.angle_between(keyframe.pose.rotation()) < 1e-3);
+        }
+        // the rotation goes the short way across the back
+        let (_, rotation) = path.sample(2.5).unwrap();
+        let expected = CameraPose {
+            yaw: 180.0,
+            ..keyframe(0.0, 0.0, 0.0).pose
+        };
+        assert!(rotation.angle_between(expected.rotation()) < 1e-3);
+        assert_eq!(path.sample(3.1), None);
+        assert_eq!(CameraPath::default().sample(0.0), None);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let path = CameraPath {
+            keyframes: vec![keyframe(0.0, 0.5, 12.5), keyframe(0.25, -3.0, -45.0)],
+        };
+        assert_eq!(path.to_string().parse::<CameraPath>(), Ok(path));
+        assert!("time\n1,0,0,0,0,0\n0,0,0,0,0,0"
+            .parse::<CameraPath>()
+            .is_err());
+        assert!("time\n0,0,0,0".parse::<CameraPath>().is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::*;
+
+pub const SCREENSHOT_DIRECTORY: &str = "screenshots";
+pub const RECORDING_DIRECTORY: &str = "recordings";
+/// Records only every nth frame when set to n.
+pub const RECORD_EVERY_ENV: &str = "SHADER_RECORD_EVERY";
+/// Captures read back or being written at most, each one holds a frame.
+const MAX_CAPTURES_IN_FLIGHT: usize = 8;
+
+/// A texture copied into a buffer that can be mapped to read it back.
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    /// Encodes the copy, the buffer can be mapped once the encoder is submitted.
+    pub fn copy(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        aspect: wgpu::TextureAspect,
+    ) -> Self {
+        let Some(bytes_per_pixel) = texture.format().block_copy_size(Some(aspect)) else {
+            panic!("{:?} can't be copied to a buffer", texture.format());
+        };
+        // the rows of the buffer are padded to the alignment wgpu requires
+        let bytes_per_row = texture.width() * bytes_per_pixel;
+        let padded_bytes_per_row = bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * texture.height()) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(texture.height()),
+                },
+            },
+            texture.size(),
+        );
+        Self {
+            buffer,
+            format: texture.format(),
+            width: texture.width(),
+            height: texture.height(),
+            bytes_per_row,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Starts mapping the buffer, `mapped` is set when it can be read.
+    pub fn map(&self, mapped: Arc<AtomicBool>) {
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("failed to map the readback buffer: {err}"),
+            });
+    }
+
+    /// The rows of the mapped buffer tightly packed, unmaps it.
+    pub fn data(&self) -> Vec<u8> {
+        let data = self
+            .buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks(self.padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..self.bytes_per_row as usize])
+            .copied()
+            .collect();
+        self.buffer.unmap();
+        data
+    }
+
+    /// The mapped buffer as 8 bit srgb, None for the formats that are not handled.
+    pub fn rgba8(&self) -> Option<image::RgbaImage> {
+        let data = self.data();
+        let data = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => data,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => data
+                .chunks(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect(),
+            // the scene color is linear, the srgb surface encodes it when presenting
+            wgpu::TextureFormat::Rgba16Float => data
+                .chunks(2)
+                .enumerate()
+                .map(|(i, half)| {
+                    let value = f32_from_f16(u16::from_le_bytes([half[0], half[1]]));
+                    let value = if i % 4 == 3 {
+                        value
+                    } else {
+                        srgb_from_linear(value)
+                    };
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+                .collect(),
+            _ => return None,
+        };
+        image::RgbaImage::from_raw(self.width, self.height, data)
+    }
+}
+
+fn f32_from_f16(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn srgb_from_linear(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// A frame to write as a png, with text chunks describing it.
+pub struct Capture {
+    pub path: PathBuf,
+    pub text: Vec<(String, String)>,
+}
+
+struct PendingCapture {
+    capture: Capture,
+    readback: Readback,
+    // missing until the copy is submitted
+    mapped: Option<Arc<AtomicBool>>,
+}
+
+/// Frames on their way back from the gpu, written to disk without
+/// stalling the renderer.
+#[derive(Default)]
+pub struct Captures {
+    requested: Vec<Capture>,
+    pending: Vec<PendingCapture>,
+    // the pngs being written on their threads
+    writing: Arc<AtomicUsize>,
+    // waited for room since the last capture that didn't have to
+    waiting: bool,
+}
+
+impl Captures {
+    /// Captures the next rendered frame.
+    pub fn request(&mut self, capture: Capture) {
+        self.requested.push(capture);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        !self.requested.is_empty()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.pending.len() + self.writing.load(Ordering::Acquire)
+    }
+
+    /// Blocks until there is room for another capture, a disk slower than the
+    /// recording then slows the renderer down instead of filling the memory.
+    pub fn wait_for_room(&mut self, device: &wgpu::Device) {
+        if self.in_flight() < MAX_CAPTURES_IN_FLIGHT {
+            self.waiting = false;
+            return;
+        }
+        if !self.waiting {
+            warn!("{MAX_CAPTURES_IN_FLIGHT} captures on their way to disk, waiting for them");
+            self.waiting = true;
+        }
+        while self.in_flight() >= MAX_CAPTURES_IN_FLIGHT {
+            self.poll(device);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Takes the requested captures from the scene color, the linear frame
+    /// before post processing and the debug ui. They are named apart so that
+    /// they aren't mistaken for what was on screen.
+    pub fn use_scene_color(&mut self) {
+        for capture in self.requested.iter_mut() {
+            let stem = capture
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            capture
+                .path
+                .set_file_name(format!("{stem}_scene_color.png"));
+            capture.text.push((
+                "Source".to_string(),
+                "scene color before post processing".to_string(),
+            ));
+        }
+    }
+
+    /// Drops the requested captures that can't be taken.
+    pub fn cancel(&mut self) {
+        self.requested.clear();
+    }
+
+    /// Copies the final frame for every request.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        for capture in self.requested.drain(..) {
+            self.pending.push(PendingCapture {
+                capture,
+                readback: Readback::copy(device, encoder, texture, wgpu::TextureAspect::All),
+                mapped: None,
+            });
+        }
+    }
+
+    /// Maps the buffers of the copies just submitted.
+    pub fn submitted(&mut self) {
+        for pending in self.pending.iter_mut().filter(|p| p.mapped.is_none()) {
+            let mapped = Arc::new(AtomicBool::new(false));
+            pending.readback.map(mapped.clone());
+            pending.mapped = Some(mapped);
+        }
+    }
+
+    /// Writes the captures that finished mapping on a thread each.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        if self.pending.is_empty() {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        let (done, pending) = self.pending.drain(..).partition(|p| {
+            p.mapped
+                .as_ref()
+                .is_some_and(|mapped| mapped.load(Ordering::Acquire))
+        });
+        self.pending = pending;
+        for PendingCapture {
+            capture, readback, ..
+        } in done
+        {
+            let Some(image) = readback.rgba8() else {
+                error!("can't capture a {:?} frame", readback.format);
+                continue;
+            };
+            self.writing.fetch_add(1, Ordering::AcqRel);
+            let writing = self.writing.clone();
+            std::thread::spawn(move || {
+                match write_png(&capture, &image) {
+                    Ok(()) => info!("wrote {:?}", capture.path),
+                    Err(err) => error!("can't write {:?}: {err}", capture.path),
+                }
+                writing.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+    }
+
+    /// Blocks until every capture is written to disk, before exiting.
+    pub fn flush(&mut self, device: &wgpu::Device) {
+        while !self.pending.is_empty() {
+            device.poll(wgpu::Maintain::Wait);
+            self.poll(device);
+        }
+        while self.writing.load(Ordering::Acquire) > 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn write_png(capture: &Capture, image: &image::RgbaImage) -> Result<(), String> {
+    if let Some(directory) = capture.path.parent() {
+        std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+    }
+    let file = std::fs::File::create(&capture.path).map_err(|err| err.to_string())?;
+    let mut encoder =
+        png::Encoder::new(std::io::BufWriter::new(file), image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in &capture.text {
+        encoder
+            .add_text_chunk(keyword.clone(), text.clone())
+            .map_err(|err| err.to_string())?;
+    }
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer
+        .write_image_data(image.as_raw())
+        .map_err(|err| err.to_string())
+}
+
+impl RenderState<'_> {
+    /// Describes the frame for a capture, the camera is in the format of
+    /// `--camera` so that the headless mode can render it again.
+    pub fn capture_text(&self, sim_state: &SimulationState) -> Vec<(String, String)> {
+        let pipelines: Vec<String> = self
+            .pipelines
+            .iter()
+            .filter(|p| !p.get_skip())
+            .map(|p| p.get_name())
+            .collect();
+        vec![
+            (
+                "Camera".to_string(),
+                CameraPose::from_sim_state(sim_state).to_string(),
+            ),
+            (
+                "Time of day".to_string(),
+                format!("{:.2}", sim_state.clock.hours()),
+            ),
+            ("Pipelines".to_string(), pipelines.join(",")),
+        ]
+    }
+
+    /// Saves the next frame in the screenshot directory, named after
+    /// the time and the camera pose.
+    pub fn screenshot(&mut self, sim_state: &SimulationState) {
+        let pose = CameraPose::from_sim_state(sim_state).to_string();
+        let path = PathBuf::from(SCREENSHOT_DIRECTORY).join(format!("{}_{pose}.png", timestamp()));
+        let text = self.capture_text(sim_state);
+        self.captures.request(Capture { path, text });
+    }
+}
+
+/// The current time for file names, without the colons windows doesn't allow.
+pub fn timestamp() -> String {
+    humantime::format_rfc3339_millis(SystemTime::now())
+        .to_string()
+        .replace(':', "-")
+}
+
+/// Writes every nth rendered frame to a numbered png sequence, meanwhile the
+/// simulation steps by a fixed timestep each frame however long it took.
+pub struct Recording {
+    pub directory: PathBuf,
+    pub every: u32,
+    pub timestep: Duration,
+    frame: u32,
+    written: u32,
+}
+
+impl Recording {
+    /// Records in a new directory named after the time.
+    pub fn new(timestep: Duration) -> Self {
+        let every = std::env::var(RECORD_EVERY_ENV)
+            .ok()
+            .and_then(|every| every.parse().ok())
+            .filter(|every| *every > 0)
+            .unwrap_or(1);
+        Self {
+            directory: PathBuf::from(RECORDING_DIRECTORY).join(timestamp()),
+            every,
+            timestep,
+            frame: 0,
+            written: 0,
+        }
+    }
+
+    /// The capture of the frame about to be rendered, if it is recorded.
+    pub fn next_frame(&mut self, text: Vec<(String, String)>) -> Option<Capture> {
+        let recorded = self.frame.is_multiple_of(self.every);
+        self.frame += 1;
+        if !recorded {
+            return None;
+        }
+        let path = self
+            .directory
+            .join(format!("frame_{:05}.png", self.written));
+        self.written += 1;
+        Some(Capture { path, text })
+    }
+}
@@ -1,25 +1,20 @@
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
+#import camera
+#import fullscreen
 
-@vertex
-fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
-    let uv = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u)) * 2.0;
-    let clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
-    return VertexOutput(clip_position, uv);
-}
-
-@group(0) @binding(0)
+@group(1) @binding(0)
 var depth_texture: texture_2d<f32>;
-@group(0) @binding(1)
+@group(1) @binding(1)
 var depth_sampler: sampler;
 
+// linear from black at the camera to white at the far plane, and where nothing was drawn,
+// the same as Projection::display_depth
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let near = 0.1;
-    let far = 1000.0;
     let depth = textureSample(depth_texture, depth_sampler, in.uv).x;
-    let r = (2.0 * near) / (far + near - depth * (far - near));
+    if is_far_depth(depth) {
+        return vec4<f32>(1.0);
+    }
+    let z = view_depth(ndc_from_uv(in.uv), depth);
+    let r = clamp(2.0 * z / (z + global.far), 0.0, 1.0);
     return vec4<f32>(vec3<f32>(r), 1.0);
 }
@@ -14,17 +14,24 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
-        let Some(depth_bind_group) = bind_groups.get("depth") else {
+        let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
         };
+        let Some(depth_bind_group) = bind_groups.get("depth") else {
+            panic!("depth bind group missing");
+        };
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("debug_depth.wgsl"));
+        let shader = include_shader!(device, "debug_depth.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
-            bind_group_layouts: &[&depth_bind_group.bind_group_layout],
+            bind_group_layouts: &[
+                &global_bind_group.bind_group_layout,
+                &depth_bind_group.bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -40,7 +47,7 @@ impl PipelineState for Pipeline {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -72,13 +79,16 @@ impl PipelineState for Pipeline {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         _clear_depth: bool,
     ) {
         let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
             return;
         };
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            return;
+        };
         let Some(depth_bind_group) = bind_groups.get("depth") else {
             return;
         };
@@ -99,10 +109,15 @@ impl PipelineState for Pipeline {
         });
 
         render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &depth_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(1, &depth_bind_group.bind_group, &[]);
         render_pass.draw(0..3, 0..1);
     }
 
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Overlay
+    }
+
     fn get_skip(&self) -> bool {
         self.skip
     }
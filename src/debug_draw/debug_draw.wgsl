@@ -0,0 +1,26 @@
+#import camera
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let clip = global.clip_from_world * vec4<f32>(in.position, 1.0);
+    // drawn after the taa resolve, so the jitter would only make the lines shimmer
+    out.clip_position = vec4<f32>(clip.xy - global.jitter.xy * clip.w, clip.zw);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
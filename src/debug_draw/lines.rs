@@ -0,0 +1,103 @@
+use std::f32::consts::TAU;
+
+use crate::*;
+
+/// Segments of each circle of a sphere.
+const SPHERE_SEGMENTS: usize = 24;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: Vec3,
+    // not a Vec4, whose alignment would pad the vertex
+    pub color: [f32; 4],
+}
+
+/// The debug lines of a frame, drawn while extracting by the simulation and
+/// the pipelines before the debug draw pipeline, which uploads them.
+#[derive(Debug, Default)]
+pub struct DebugLines {
+    vertices: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    /// The vertices drawn so far, two for each line.
+    pub fn take(&mut self) -> Vec<LineVertex> {
+        std::mem::take(&mut self.vertices)
+    }
+
+    /// Draws a line in world space for one frame, depth tested against the scene.
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec4) {
+        let color = color.to_array();
+        self.vertices.push(LineVertex { position: a, color });
+        self.vertices.push(LineVertex { position: b, color });
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec4) {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        self.box_edges(corner, color);
+    }
+
+    /// Three great circles around the center.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        for axes in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+            let point = |i: usize| {
+                let angle = i as f32 / SPHERE_SEGMENTS as f32 * TAU;
+                center + (axes.0 * angle.cos() + axes.1 * angle.sin()) * radius
+            };
+            for i in 0..SPHERE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    /// The edges of the volume a camera sees, from the inverse of its
+    /// view-projection matrix.
+    pub fn frustum(&mut self, world_from_clip: Mat4, color: Vec4) {
+        let corner = |i: usize| {
+            world_from_clip.project_point3(Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            ))
+        };
+        self.box_edges(corner, color);
+    }
+
+    // the corners are indexed by their bits along x, y and z
+    fn box_edges(&mut self, corner: impl Fn(usize) -> Vec3, color: Vec4) {
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batched_lines() {
+        let mut lines = DebugLines::default();
+        lines.aabb(Vec3::ZERO, Vec3::ONE, Vec4::ONE);
+        lines.sphere(Vec3::ZERO, 1.0, Vec4::ONE);
+        lines.frustum(Mat4::IDENTITY, Vec4::ONE);
+        let vertices = lines.take();
+        assert_eq!(vertices.len(), (12 + 3 * SPHERE_SEGMENTS + 12) * 2);
+        // every edge of the box has unit length
+        for pair in vertices[..24].chunks(2) {
+            assert_eq!(pair[0].position.distance(pair[1].position), 1.0);
+        }
+        assert!(lines.take().is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod lines;
+pub mod pipeline;
+pub use lines::*;
+pub use pipeline::*;
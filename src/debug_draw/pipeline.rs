@@ -0,0 +1,192 @@
+use super::*;
+use crate::*;
+
+// draws the lines of the debug draw calls over the post processed scene,
+// hidden behind what is nearer to the camera
+pub struct Pipeline {
+    pipeline: wgpu::RenderPipeline,
+    skip: bool,
+    //
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+pub const PIPELINE_NAME: &str = "Debug Draw";
+
+/// Vertices drawn in a frame, the lines past it are dropped.
+const MAX_LINE_VERTICES: usize = 1 << 16;
+
+impl LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+impl PipelineState for Pipeline {
+    fn get_name(&self) -> String {
+        PIPELINE_NAME.to_string()
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> Self {
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            panic!("global bind group missing");
+        };
+
+        let shader = include_shader!(device, "debug_draw.wgsl");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
+            bind_group_layouts: &[&global_bind_group.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_COLOR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // tested against the depth of the scene without writing to it
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: projection.depth_compare_or_equal(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Vertex Buffer")),
+            size: (MAX_LINE_VERTICES * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            skip: false,
+            vertex_buffer,
+            vertex_count: 0,
+        }
+    }
+
+    fn extract(
+        &mut self,
+        _sim_state: &mut SimulationState,
+        frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
+        // taken even while skipped so that the calls don't pile up
+        let mut lines = frame.lines.take();
+        if self.skip {
+            self.vertex_count = 0;
+            return;
+        }
+        if lines.len() > MAX_LINE_VERTICES {
+            warn!(
+                "{} debug line vertices, drawing the first {MAX_LINE_VERTICES}",
+                lines.len()
+            );
+            lines.truncate(MAX_LINE_VERTICES);
+        }
+        self.vertex_count = lines.len() as u32;
+        if !lines.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&lines));
+        }
+    }
+
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        _clear_depth: bool,
+    ) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+            return;
+        };
+        let Some(Attachment::Depth(depth_attachment)) = attachments.get("depth") else {
+            return;
+        };
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &color_attachment.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_attachment.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Overlay
+    }
+
+    fn get_skip(&self) -> bool {
+        self.skip
+    }
+
+    fn set_skip(&mut self, skip: bool) {
+        self.skip = skip
+    }
+}
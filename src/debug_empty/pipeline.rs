@@ -14,10 +14,11 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        _bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        _bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
-        let shader = device.create_shader_module(wgpu::include_wgsl!("debug_empty.wgsl"));
+        let shader = include_shader!(device, "debug_empty.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
             bind_group_layouts: &[],
@@ -36,7 +37,7 @@ impl PipelineState for Pipeline {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -56,7 +57,7 @@ impl PipelineState for Pipeline {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        _bind_groups: &HashMap<String, BindGroupState>,
+        _bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         _clear_depth: bool,
     ) {
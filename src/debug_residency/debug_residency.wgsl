@@ -0,0 +1,60 @@
+#import camera
+#import fullscreen
+
+// the size of the chunks grid in raycast_hierarchy_feedback/residency.rs
+const GRID_SIDE: u32 = 8u;
+const MINIMAP_MARGIN: f32 = 8.0;
+// in pixels before scaling, between the tiles of the layers
+const CELL_SIZE: f32 = 6.0;
+const TILE_GAP: f32 = 4.0;
+
+struct ResidencyUniform {
+    // the state of each slot, zero for the empty ones
+    slots: array<vec4<u32>, 128>,
+};
+@group(1) @binding(0)
+var<uniform> residency: ResidencyUniform;
+
+// the colors of ResidencyState::color
+fn state_color(state: u32) -> vec3<f32> {
+    switch state {
+        case 1u: { return vec3<f32>(0.3, 0.9, 0.3); }
+        case 2u: { return vec3<f32>(1.0, 0.85, 0.3); }
+        case 3u: { return vec3<f32>(0.55, 0.55, 0.55); }
+        case 4u: { return vec3<f32>(0.9, 0.4, 1.0); }
+        default: { return vec3<f32>(0.02, 0.02, 0.02); }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let px = in.uv * global.viewport_size.xy;
+
+    // the tiles of the layers side by side from the bottom left corner,
+    // x to the right and z down in each tile, scaled like the debug ui text
+    let scale = max(1.0, floor(global.viewport_size.y / 540.0));
+    let tile_size = f32(GRID_SIDE) * CELL_SIZE + TILE_GAP;
+    let origin = vec2<f32>(MINIMAP_MARGIN, global.viewport_size.y - MINIMAP_MARGIN - tile_size * scale);
+    let minimap_px = (px - origin) / scale;
+    if minimap_px.x < 0.0 || minimap_px.y < 0.0 || minimap_px.y >= tile_size {
+        return vec4<f32>(0.0);
+    }
+    let layer = u32(minimap_px.x / tile_size);
+    if layer >= GRID_SIDE {
+        return vec4<f32>(0.0);
+    }
+    let tile_px = minimap_px - vec2<f32>(f32(layer) * tile_size, 0.0) - TILE_GAP * 0.5;
+    let cell = vec2<u32>(tile_px / CELL_SIZE);
+    if tile_px.x < 0.0 || tile_px.y < 0.0 || cell.x >= GRID_SIDE || cell.y >= GRID_SIDE {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.6);
+    }
+    // a pixel of background around each cell
+    let in_cell = tile_px - vec2<f32>(cell) * CELL_SIZE;
+    if in_cell.x < 1.0 || in_cell.y < 1.0 {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.6);
+    }
+
+    let slot = cell.x + layer * GRID_SIDE + cell.y * GRID_SIDE * GRID_SIDE;
+    let state = residency.slots[slot / 4u][slot % 4u];
+    return vec4<f32>(state_color(state), 1.0);
+}
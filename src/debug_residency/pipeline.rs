@@ -0,0 +1,220 @@
+use bytemuck::Zeroable;
+
+use crate::{
+    raycast_hierarchy_feedback::{ResidencyState, CHUNKS_GRID_VOLUME},
+    *,
+};
+
+// shows the streaming of raycast_hierarchy_feedback: the borders of the
+// chunks it knows of colored by their state, and a minimap of the chunks
+// grid with a tile for each layer from the bottom one
+pub struct Pipeline {
+    pipeline: wgpu::RenderPipeline,
+    skip: bool,
+    // warned that there was no residency, until there is one again
+    missing_residency: bool,
+    //
+    residency_bind_group: BindGroupState,
+}
+
+pub const PIPELINE_NAME: &str = "Debug Residency";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ResidencyUniform {
+    // the state of each slot of the chunks grid, zero for the empty ones
+    pub slots: [[u32; 4]; CHUNKS_GRID_VOLUME / 4],
+}
+
+pub const RESIDENCY_BIND_GROUP_LAYOUT: &[wgpu::BindGroupLayoutEntry] =
+    &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ResidencyUniform>() as u64),
+        },
+        count: None,
+    }];
+
+impl ResidencyState {
+    /// The color of the chunk borders, the minimap uses the same ones.
+    pub fn color(&self) -> Vec4 {
+        match self {
+            ResidencyState::Resident => Vec4::new(0.3, 0.9, 0.3, 1.0),
+            ResidencyState::Requested => Vec4::new(1.0, 0.85, 0.3, 1.0),
+            ResidencyState::Evicted => Vec4::new(0.55, 0.55, 0.55, 1.0),
+            ResidencyState::Dirty => Vec4::new(0.9, 0.4, 1.0, 1.0),
+        }
+    }
+}
+
+impl PipelineState for Pipeline {
+    fn get_name(&self) -> String {
+        PIPELINE_NAME.to_string()
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> Self {
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            panic!("global bind group missing");
+        };
+
+        let residency_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Residency Buffer"),
+            contents: bytemuck::cast_slice(&[ResidencyUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let residency_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: RESIDENCY_BIND_GROUP_LAYOUT,
+                label: Some("residency_bind_group_layout"),
+            });
+        let residency_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &residency_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: residency_buffer.as_entire_binding(),
+            }],
+            label: Some("residency_bind_group"),
+        });
+        let residency_bind_group = BindGroupState {
+            buffer: vec![residency_buffer],
+            bind_group: residency_bind_group,
+            bind_group_layout: residency_bind_group_layout,
+        };
+
+        let shader = include_shader!(device, "debug_residency.wgsl");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
+            bind_group_layouts: &[
+                &global_bind_group.bind_group_layout,
+                &residency_bind_group.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_COLOR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            skip: true,
+            missing_residency: false,
+            residency_bind_group,
+        }
+    }
+
+    fn extract(
+        &mut self,
+        _sim_state: &mut SimulationState,
+        frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
+        if self.skip {
+            return;
+        }
+        // the hierarchy raycast hands it over when it extracts before the overlays
+        let Some(residency) = &frame.residency else {
+            if !self.missing_residency {
+                warn!(
+                    "no chunk residency to show, {} must run before {PIPELINE_NAME}",
+                    raycast_hierarchy_feedback::PIPELINE_NAME
+                );
+                self.missing_residency = true;
+            }
+            queue.write_buffer(
+                &self.residency_bind_group.buffer[0],
+                0,
+                bytemuck::cast_slice(&[ResidencyUniform::zeroed()]),
+            );
+            return;
+        };
+        self.missing_residency = false;
+        let chunk_size = Vec3::splat(CHUNK_SIDE as f32);
+        for (chunk_pos, state) in &residency.chunks {
+            let min = chunk_pos.as_vec3();
+            frame.lines.aabb(min, min + chunk_size, state.color());
+        }
+
+        let mut uniform = ResidencyUniform::zeroed();
+        for (i, state) in residency.slots().into_iter().enumerate() {
+            uniform.slots[i / 4][i % 4] = state.map_or(0, |state| state as u32);
+        }
+        queue.write_buffer(
+            &self.residency_bind_group.buffer[0],
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+    }
+
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        _clear_depth: bool,
+    ) {
+        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+            return;
+        };
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &color_attachment.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.residency_bind_group.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Overlay
+    }
+
+    fn get_skip(&self) -> bool {
+        self.skip
+    }
+
+    fn set_skip(&mut self, skip: bool) {
+        self.skip = skip
+    }
+}
@@ -1,42 +1,43 @@
-struct GlobalUniform {
-    viewport_size: vec4<f32>,
-    view_world_position: vec4<f32>,
-    world_from_clip: mat4x4<f32>,
-    clip_from_world: mat4x4<f32>,
-    view_from_clip: mat4x4<f32>,
-    clip_from_view: mat4x4<f32>,
-    view_from_world: mat4x4<f32>,
-    world_from_view: mat4x4<f32>,
-};
-@group(0) @binding(0)
-var<uniform> global: GlobalUniform;
+#import camera
+#import fullscreen
+
+// the size of the text grid in text.rs
+const TEXT_COLUMNS: u32 = 128u;
+const TEXT_ROWS: u32 = 32u;
+const TEXT_MARGIN: f32 = 8.0;
 
-// for stride reason, wrap u32 to set correct array stride at 16, otherwise it's 4
-struct wrapped_u32 {
-  @size(16) elem: u32
-}
 struct UiUniform {
-    pipelines_skip: array<wrapped_u32, 256>,
-    pipelines_num: u32,
+    // two cells per u32, the ascii code in the low byte and the color in the high byte
+    text: array<vec4<u32>, 512>,
+    // 8x8 glyphs from space to tilde, four rows per u32 with the leftmost pixel in the lowest bit
+    font: array<vec4<u32>, 48>,
+    shader_error: u32,
 };
 @group(1) @binding(0)
 var<uniform> ui: UiUniform;
 
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(1) uv: vec2<f32>,
-};
+fn palette(color: u32) -> vec3<f32> {
+    switch color {
+        case 1u: { return vec3<f32>(0.3, 0.9, 0.3); }
+        case 2u: { return vec3<f32>(1.0, 0.3, 0.3); }
+        case 3u: { return vec3<f32>(1.0, 0.85, 0.3); }
+        case 4u: { return vec3<f32>(0.55, 0.55, 0.55); }
+        default: { return vec3<f32>(1.0, 1.0, 1.0); }
+    }
+}
 
-@vertex
-fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
-    let uv = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u)) * 2.0;
-    let clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
-    return VertexOutput(clip_position, uv);
+// zero for the cells without text
+fn text_cell(cell: vec2<u32>) -> u32 {
+    let index = cell.y * TEXT_COLUMNS + cell.x;
+    let word = ui.text[index / 8u][(index / 2u) % 4u];
+    return (word >> (16u * (index % 2u))) & 0xffffu;
 }
 
-fn in_box(pos: vec2<f32>, size: vec2<f32>, t: vec2<f32>) -> bool {
-    return pos.x > t.x && pos.x < t.x + size.x && //
-        pos.y > t.y && pos.y < t.y + size.y;
+fn glyph_pixel(code: u32, pixel: vec2<u32>) -> bool {
+    let byte = (code - 32u) * 8u + pixel.y;
+    let word = ui.font[byte / 16u][(byte / 4u) % 4u];
+    let row = (word >> (8u * (byte % 4u))) & 0xffu;
+    return ((row >> pixel.x) & 1u) == 1u;
 }
 
 @fragment
@@ -44,12 +45,24 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // convert uv from (0.0..1.0, 0.0..1.0) to (0..viewport_size.x, 0..viewport_size.y)
     let px = in.uv * global.viewport_size.xy;
 
-    for (var i = 0u; i < ui.pipelines_num; i++) {
-        if in_box(vec2(100.0 + f32(i) * 25, 200.0), vec2(20.0, 20.0), px) {
-            if ui.pipelines_skip[i].elem == 1 {
-                return vec4<f32>(1.0, 0.0, 0.0, 1.0);
-            } else {
-                return vec4<f32>(0.0, 0.5, 0.0, 1.0);
+    // a reloaded shader failed to compile
+    let border = min(px, global.viewport_size.xy - px);
+    if ui.shader_error != 0u && min(border.x, border.y) < 4.0 {
+        return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    }
+
+    // the text grid from the top left corner, the glyphs grow with the viewport
+    let scale = max(1.0, floor(global.viewport_size.y / 540.0));
+    let text_px = (px - TEXT_MARGIN) / scale;
+    if text_px.x >= 0.0 && text_px.y >= 0.0 {
+        let cell = vec2<u32>(text_px / 8.0);
+        if cell.x < TEXT_COLUMNS && cell.y < TEXT_ROWS {
+            let value = text_cell(cell);
+            if value != 0u {
+                if glyph_pixel(value & 0xffu, vec2<u32>(text_px) % 8u) {
+                    return vec4<f32>(palette(value >> 8u), 1.0);
+                }
+                return vec4<f32>(0.0, 0.0, 0.0, 0.6);
             }
         }
     }
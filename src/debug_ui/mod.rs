@@ -1,2 +1,6 @@
+pub mod panel;
 pub mod pipeline;
+pub mod text;
+pub use panel::*;
 pub use pipeline::*;
+pub use text::*;
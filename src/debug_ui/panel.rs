@@ -0,0 +1,404 @@
+use std::ops::Range;
+
+use winit::{event::MouseButton, window::CursorGrabMode};
+
+use super::*;
+use crate::*;
+
+/// Opens the panel and releases the cursor.
+pub const PANEL_KEY: KeyCode = KeyCode::Tab;
+// the pipeline names are padded so that the arrows line up
+const NAME_COLUMNS: usize = 32;
+const SLIDER_LABEL_COLUMNS: usize = 18;
+const SLIDER_COLUMNS: usize = 24;
+
+/// A value the panel adjusts with a slider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parameter {
+    Exposure,
+    BloomIntensity,
+    BloomThreshold,
+    LutStrength,
+    VignetteStrength,
+    TimeOfDay,
+    ClockSpeed,
+    FieldOfView,
+    NearPlane,
+    FarPlane,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 10] = [
+        Parameter::Exposure,
+        Parameter::BloomIntensity,
+        Parameter::BloomThreshold,
+        Parameter::LutStrength,
+        Parameter::VignetteStrength,
+        Parameter::TimeOfDay,
+        Parameter::ClockSpeed,
+        Parameter::FieldOfView,
+        Parameter::NearPlane,
+        Parameter::FarPlane,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Parameter::Exposure => "exposure",
+            Parameter::BloomIntensity => "bloom intensity",
+            Parameter::BloomThreshold => "bloom threshold",
+            Parameter::LutStrength => "lut strength",
+            Parameter::VignetteStrength => "vignette strength",
+            Parameter::TimeOfDay => "time of day",
+            Parameter::ClockSpeed => "clock speed",
+            Parameter::FieldOfView => "field of view",
+            Parameter::NearPlane => "near plane",
+            Parameter::FarPlane => "far plane",
+        }
+    }
+
+    pub fn range(&self) -> Range<f32> {
+        match self {
+            Parameter::Exposure => 0.0..4.0,
+            Parameter::BloomIntensity => 0.0..0.5,
+            Parameter::BloomThreshold => 0.0..4.0,
+            Parameter::LutStrength | Parameter::VignetteStrength => 0.0..1.0,
+            Parameter::TimeOfDay => 0.0..24.0,
+            Parameter::ClockSpeed => 0.0..16.0,
+            // in degrees
+            Parameter::FieldOfView => 30.0..120.0,
+            Parameter::NearPlane => 0.01..1.0,
+            Parameter::FarPlane => 100.0..5000.0,
+        }
+    }
+
+    pub fn get(&self, render_state: &RenderState, sim_state: &SimulationState) -> f32 {
+        let post = &render_state.uniform_post;
+        match self {
+            Parameter::Exposure => post.exposure,
+            Parameter::BloomIntensity => post.bloom_intensity,
+            Parameter::BloomThreshold => post.bloom_threshold,
+            Parameter::LutStrength => post.lut_strength,
+            Parameter::VignetteStrength => post.vignette_strength,
+            Parameter::TimeOfDay => sim_state.clock.hours(),
+            Parameter::ClockSpeed => sim_state.clock.speed,
+            Parameter::FieldOfView => render_state.projection.fov_y.to_degrees(),
+            Parameter::NearPlane => render_state.projection.near,
+            Parameter::FarPlane => render_state.projection.far,
+        }
+    }
+
+    pub fn set(&self, render_state: &mut RenderState, sim_state: &mut SimulationState, value: f32) {
+        let post = &mut render_state.uniform_post;
+        match self {
+            Parameter::Exposure => post.exposure = value,
+            Parameter::BloomIntensity => post.bloom_intensity = value,
+            Parameter::BloomThreshold => post.bloom_threshold = value,
+            Parameter::LutStrength => post.lut_strength = value,
+            Parameter::VignetteStrength => post.vignette_strength = value,
+            Parameter::TimeOfDay => sim_state.clock.time_of_day = (value / 24.0).rem_euclid(1.0),
+            Parameter::ClockSpeed => sim_state.clock.speed = value,
+            Parameter::FieldOfView => render_state.projection.fov_y = value.to_radians(),
+            Parameter::NearPlane => render_state.projection.near = value,
+            Parameter::FarPlane => render_state.projection.far = value,
+        }
+    }
+}
+
+/// What a click on the panel does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelAction {
+    TogglePipeline(usize),
+    SwapPipelines(usize, usize),
+    Slider(Parameter),
+    CycleViewMode(usize),
+}
+
+/// Cells of the text grid that react to a click.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub action: PanelAction,
+}
+
+impl Hit {
+    pub fn contains(&self, cell: Vec2) -> bool {
+        cell.y >= 0.0
+            && cell.y as usize == self.row
+            && cell.x >= self.columns.start as f32
+            && cell.x < self.columns.end as f32
+    }
+
+    /// Where the cell is along the columns, from 0 to 1.
+    pub fn fraction(&self, cell: Vec2) -> f32 {
+        let length = (self.columns.end - self.columns.start) as f32;
+        ((cell.x - self.columns.start as f32) / length).clamp(0.0, 1.0)
+    }
+}
+
+/// The debug ui while the cursor is released to click it.
+#[derive(Default)]
+pub struct DebugPanel {
+    pub open: bool,
+    // the slider under the cursor since the button was pressed
+    dragging: Option<Hit>,
+}
+
+impl RenderState<'_> {
+    /// The text of the debug ui and, when the panel is open, the cells
+    /// that can be clicked. Hit testing uses the same layout so that the
+    /// two can't disagree.
+    pub fn debug_layout(&self, sim_state: &SimulationState) -> (TextGrid, Vec<Hit>) {
+        let mut grid = TextGrid::default();
+        let mut hits = vec![];
+        let open = self.debug_panel.open;
+
+        let fps = 1.0 / self.frame_time_average.max(f32::EPSILON);
+        let frame = format!("{fps:.0} fps {:.2}ms", self.frame_time_average * 1000.0);
+        // slower than 30 fps stands out
+        let color = if fps < 30.0 {
+            TextColor::Yellow
+        } else {
+            TextColor::White
+        };
+        let column = grid.write(0, 0, &frame, color);
+        let hint = if open {
+            "  [tab] close the panel"
+        } else {
+            "  [tab] open the panel"
+        };
+        grid.write(0, column, hint, TextColor::Gray);
+        let mut camera = format!("camera {}", CameraPose::from_sim_state(sim_state));
+        if sim_state.camera_mode.is_orthographic() {
+            camera += &format!(
+                " {} x{:.1}",
+                sim_state.camera_mode.label(),
+                sim_state.camera_zoom
+            );
+        }
+        match &sim_state.camera_path {
+            Some(CameraPathState::Recording { path, .. }) => {
+                camera += &format!(" recording {} keyframes", path.keyframes.len());
+            }
+            Some(CameraPathState::Playing { path, time }) => {
+                camera += &format!(" playing {time:.1}/{:.1}s", path.duration());
+            }
+            None => {}
+        }
+        grid.write(1, 0, &camera, TextColor::White);
+        let world = format!(
+            "chunks {}, time {:.2}h, {} shading, {} z",
+            sim_state.universe.chunks.len(),
+            sim_state.clock.hours(),
+            if self.deferred { "deferred" } else { "forward" },
+            if self.projection.reverse_z {
+                "reverse"
+            } else {
+                "standard"
+            }
+        );
+        grid.write(2, 0, &world, TextColor::White);
+
+        let mut row = 4;
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            let key = PIPELINE_KEYS.get(i).map_or(" ", |(_, label)| label);
+            let (state, color) = if pipeline.get_skip() {
+                ("off", TextColor::Gray)
+            } else {
+                ("on ", TextColor::Green)
+            };
+            let column = grid.write(row, 0, &format!("[{key}] {state} "), color);
+            let name = if open {
+                format!("{:NAME_COLUMNS$}", pipeline.get_name())
+            } else {
+                pipeline.get_name()
+            };
+            let column = grid.write(row, column, &name, color);
+            if open {
+                hits.push(Hit {
+                    row,
+                    columns: 0..column,
+                    action: PanelAction::TogglePipeline(i),
+                });
+                let reorderable = |j: usize| {
+                    let stage = self.pipelines[j].get_stage();
+                    stage.is_reorderable() && stage == pipeline.get_stage()
+                };
+                if i > 0 && reorderable(i - 1) {
+                    let up = grid.write(row, column, " ^", TextColor::White);
+                    hits.push(Hit {
+                        row,
+                        columns: column..up,
+                        action: PanelAction::SwapPipelines(i - 1, i),
+                    });
+                }
+                if i + 1 < self.pipelines.len() && reorderable(i + 1) {
+                    let down = grid.write(row, column + 2, " v", TextColor::White);
+                    hits.push(Hit {
+                        row,
+                        columns: column + 2..down,
+                        action: PanelAction::SwapPipelines(i, i + 1),
+                    });
+                }
+            }
+            if let Some(view_mode) = pipeline.get_view_mode() {
+                if open {
+                    let view = format!(" view {}", view_mode.label());
+                    let end = grid.write(row, column + 4, &view, TextColor::Yellow);
+                    hits.push(Hit {
+                        row,
+                        columns: column + 5..end,
+                        action: PanelAction::CycleViewMode(i),
+                    });
+                } else if view_mode != ViewMode::Textured {
+                    let view = format!(" ({})", view_mode.label());
+                    grid.write(row, column, &view, TextColor::Yellow);
+                }
+            }
+            row += 1;
+        }
+
+        if open {
+            row += 1;
+            for parameter in Parameter::ALL {
+                let label = format!("{:SLIDER_LABEL_COLUMNS$}", parameter.label());
+                let column = grid.write(row, 0, &label, TextColor::White);
+                let value = parameter.get(self, sim_state);
+                let range = parameter.range();
+                let fraction = ((value - range.start) / (range.end - range.start)).clamp(0.0, 1.0);
+                let filled = (fraction * SLIDER_COLUMNS as f32).round() as usize;
+                let bar = "=".repeat(filled) + &"-".repeat(SLIDER_COLUMNS - filled);
+                let end = grid.write(row, column, &bar, TextColor::Yellow);
+                hits.push(Hit {
+                    row,
+                    columns: column..end,
+                    action: PanelAction::Slider(parameter),
+                });
+                grid.write(row, end, &format!(" {value:.2}"), TextColor::White);
+                row += 1;
+            }
+        }
+
+        if let Some(err) = self.pipeline_sources.iter().find_map(|s| s.error.as_ref()) {
+            let first_line = err.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            grid.write(row + 1, 0, first_line.trim(), TextColor::Red);
+        }
+        (grid, hits)
+    }
+
+    /// The cursor is confined to the window unless the panel is open.
+    pub fn grab_cursor(&self) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let mode = if self.debug_panel.open {
+            CursorGrabMode::None
+        } else {
+            CursorGrabMode::Confined
+        };
+        if let Err(err) = window.set_cursor_grab(mode) {
+            warn!("can't set the cursor grab to {mode:?}: {err}");
+        }
+    }
+
+    pub fn toggle_debug_panel(&mut self) {
+        self.debug_panel.open = !self.debug_panel.open;
+        self.debug_panel.dragging = None;
+        self.grab_cursor();
+    }
+
+    /// Clicks pipelines and drags sliders with the left mouse button.
+    pub fn debug_panel_input(&mut self, sim_state: &mut SimulationState, input_state: &InputState) {
+        if !self.debug_panel.open {
+            return;
+        }
+        if !input_state.is_mouse_pressed(&MouseButton::Left) {
+            self.debug_panel.dragging = None;
+            return;
+        }
+        let cell = cell_at(input_state.mouse_pos, self.config.height as f32);
+
+        if let Some(hit) = &self.debug_panel.dragging {
+            if let PanelAction::Slider(parameter) = hit.action {
+                let range = parameter.range();
+                let value = range.start + hit.fraction(cell) * (range.end - range.start);
+                parameter.set(self, sim_state, value);
+            }
+            return;
+        }
+        if !input_state.is_mouse_just_pressed(&MouseButton::Left) {
+            return;
+        }
+        let (_, hits) = self.debug_layout(sim_state);
+        let Some(hit) = hits.into_iter().find(|hit| hit.contains(cell)) else {
+            return;
+        };
+        match hit.action {
+            PanelAction::TogglePipeline(i) => {
+                let skip = self.pipelines[i].get_skip();
+                self.pipelines[i].set_skip(!skip);
+            }
+            // the sources stay in the order of the pipelines they rebuild,
+            // the layout only offers swaps within a reorderable stage
+            PanelAction::SwapPipelines(a, b) => {
+                self.pipelines.swap(a, b);
+                self.pipeline_sources.swap(a, b);
+            }
+            PanelAction::Slider(parameter) => {
+                let range = parameter.range();
+                let value = range.start + hit.fraction(cell) * (range.end - range.start);
+                parameter.set(self, sim_state, value);
+                self.debug_panel.dragging = Some(hit);
+            }
+            PanelAction::CycleViewMode(i) => {
+                let view_mode = self.pipelines[i].get_view_mode().unwrap_or_default();
+                self.pipelines[i].set_view_mode(view_mode.next());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_cells() {
+        let hit = Hit {
+            row: 3,
+            columns: 10..20,
+            action: PanelAction::TogglePipeline(0),
+        };
+        // the viewport is 1080 high, so the glyphs are scaled twice
+        let cell = |x: f32, y: f32| cell_at(Vec2::new(x, y), 1080.0);
+        assert!(hit.contains(cell(TEXT_MARGIN + 16.0 * 10.0, TEXT_MARGIN + 16.0 * 3.5)));
+        assert!(!hit.contains(cell(TEXT_MARGIN + 16.0 * 20.0, TEXT_MARGIN + 16.0 * 3.5)));
+        assert!(!hit.contains(cell(TEXT_MARGIN + 16.0 * 15.0, TEXT_MARGIN + 16.0 * 4.0)));
+        assert!(!hit.contains(cell(0.0, 0.0)));
+        assert_eq!(hit.fraction(cell(TEXT_MARGIN + 16.0 * 15.0, 0.0)), 0.5);
+        assert_eq!(hit.fraction(cell(0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn swaps_stay_within_a_stage() {
+        let Some(mut render_state) = pollster::block_on(RenderState::new_headless(64, 64, true))
+        else {
+            panic!("no fallback adapter to lay out the panel with");
+        };
+        render_state.debug_panel.open = true;
+        let (_, hits) = render_state.debug_layout(&SimulationState::new());
+        let swaps: Vec<_> = hits
+            .iter()
+            .filter_map(|hit| match hit.action {
+                PanelAction::SwapPipelines(a, b) => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+        assert!(!swaps.is_empty());
+        for (a, b) in swaps {
+            let stage = render_state.pipelines[a].get_stage();
+            assert!(stage.is_reorderable());
+            assert_eq!(stage, render_state.pipelines[b].get_stage());
+        }
+    }
+}
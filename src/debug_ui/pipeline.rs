@@ -14,8 +14,9 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
         let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
@@ -24,7 +25,7 @@ impl PipelineState for Pipeline {
             panic!("ui bind group missing");
         };
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("debug_ui.wgsl"));
+        let shader = include_shader!(device, "debug_ui.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
             bind_group_layouts: &[
@@ -46,7 +47,7 @@ impl PipelineState for Pipeline {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -66,7 +67,7 @@ impl PipelineState for Pipeline {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         _clear_depth: bool,
     ) {
@@ -99,6 +100,10 @@ impl PipelineState for Pipeline {
         render_pass.draw(0..3, 0..1);
     }
 
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Overlay
+    }
+
     fn get_skip(&self) -> bool {
         self.skip
     }
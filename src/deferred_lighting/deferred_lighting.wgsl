@@ -0,0 +1,56 @@
+#import camera
+#import lighting
+#import fullscreen
+
+@group(1) @binding(0)
+var gbuffer_albedo: texture_2d<f32>;
+@group(1) @binding(1)
+var gbuffer_normal: texture_2d<f32>;
+@group(1) @binding(2)
+var gbuffer_block_id: texture_2d<u32>;
+
+@group(2) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var depth_sampler: sampler;
+
+@group(3) @binding(0)
+var gi_history: texture_2d<f32>;
+
+// the block id of the debug views in raycast_view.wgsl
+const UNLIT_BLOCK_ID: u32 = 0xffffffffu;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    // the debug views of the raycasters are drawn as they are, misses included
+    let albedo = textureLoad(gbuffer_albedo, texel, 0);
+    if textureLoad(gbuffer_block_id, texel, 0).x == UNLIT_BLOCK_ID {
+        return albedo;
+    }
+
+    let depth = textureLoad(depth_texture, texel, 0).x;
+    if is_far_depth(depth) {
+        // nothing was written to the gbuffer
+        return vec4<f32>(0.0);
+    }
+
+    // the pipelines store the visibility of the sun in the normal's w
+    let normal = textureLoad(gbuffer_normal, texel, 0);
+    let sun_visibility = normal.w;
+
+    // the accumulated gi is used only where it was written for this same surface,
+    // it stores the linear depth of the pixel that can be compared against the depth buffer
+    var indirect = lighting.ambient_color.rgb;
+    let history = textureLoad(gi_history, texel, 0);
+    let ndc = ndc_from_uv(in.uv);
+    let linear_depth = view_depth(ndc, depth);
+    if abs(history.w - linear_depth) < linear_depth * 0.01 {
+        indirect = history.rgb;
+    }
+
+    // every pipeline is lit by the same directional light
+    let diffuse = max(dot(normal.xyz, lighting.light_direction.xyz), 0.0) * sun_visibility;
+    let light = indirect + lighting.light_color.rgb * diffuse;
+    return vec4<f32>(albedo.rgb * light, albedo.a);
+}
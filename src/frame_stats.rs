@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::*;
+
+pub const FRAME_STATS_DIRECTORY: &str = "frame_stats";
+/// Frames the summary is computed over.
+const FRAME_STATS_WINDOW: usize = 600;
+pub const FRAME_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The cpu frame times over the rolling window, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSummary {
+    pub frames: usize,
+    pub min: f32,
+    pub avg: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+impl std::fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames, min {:.2}ms avg {:.2}ms p50 {:.2}ms p95 {:.2}ms p99 {:.2}ms max {:.2}ms",
+            self.frames, self.min, self.avg, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+/// Collects the time of every rendered frame and summarizes the last
+/// FRAME_STATS_WINDOW of them, the others are only in the csv.
+#[derive(Default)]
+pub struct FrameStats {
+    window: VecDeque<f32>,
+    frames: usize,
+    csv: Option<FrameCsv>,
+}
+
+// every frame is written as it comes, with the simulation time it was rendered at
+struct FrameCsv {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl FrameStats {
+    /// Also writes every frame time to a csv at the path.
+    pub fn with_csv(path: PathBuf) -> Result<Self, String> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+        }
+        let file = File::create(&path).map_err(|err| err.to_string())?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "frame,simulation_time_s,frame_time_ms").map_err(|err| err.to_string())?;
+        Ok(Self {
+            csv: Some(FrameCsv { path, writer }),
+            ..Self::default()
+        })
+    }
+
+    /// A csv in the frame stats directory named after the time.
+    pub fn csv_path() -> PathBuf {
+        PathBuf::from(FRAME_STATS_DIRECTORY).join(format!("{}.csv", timestamp()))
+    }
+
+    pub fn add(&mut self, frame_time: Duration, simulation_time: Duration) {
+        let milliseconds = frame_time.as_secs_f32() * 1000.0;
+        if self.window.len() == FRAME_STATS_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(milliseconds);
+        if let Some(csv) = &mut self.csv {
+            let row = writeln!(
+                csv.writer,
+                "{},{:.3},{milliseconds:.3}",
+                self.frames,
+                simulation_time.as_secs_f32()
+            );
+            if let Err(err) = row {
+                error!("can't write the frame stats to {:?}: {err}", csv.path);
+                self.csv = None;
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// None before the first frame.
+    pub fn summary(&self) -> Option<FrameSummary> {
+        let mut sorted: Vec<f32> = self.window.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let (min, max) = (*sorted.first()?, *sorted.last()?);
+        // nearest rank
+        let percentile = |p: f32| {
+            let rank = (p / 100.0 * sorted.len() as f32).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(FrameSummary {
+            frames: sorted.len(),
+            min,
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max,
+        })
+    }
+
+    pub fn log(&self) {
+        if let Some(summary) = self.summary() {
+            info!(target: "timing", "{summary}");
+        }
+    }
+
+    /// Flushes the csv and returns its path, None without one.
+    pub fn finish_csv(&mut self) -> Option<Result<PathBuf, String>> {
+        let mut csv = self.csv.take()?;
+        Some(
+            csv.writer
+                .flush()
+                .map(|()| csv.path)
+                .map_err(|err| err.to_string()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.summary(), None);
+        for milliseconds in (1..=100).rev() {
+            stats.add(Duration::from_millis(milliseconds), Duration::ZERO);
+        }
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.frames, 100);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.p50, 50.0);
+        assert_eq!(summary.p95, 95.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.max, 100.0);
+        assert!((summary.avg - 50.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn streamed_csv() {
+        let path = std::env::temp_dir().join(format!("frame_stats_{}.csv", std::process::id()));
+        let mut stats = FrameStats::with_csv(path.clone()).unwrap();
+        stats.add(Duration::from_millis(16), Duration::from_millis(20));
+        stats.add(Duration::from_millis(17), Duration::from_millis(40));
+        assert_eq!(stats.finish_csv(), Some(Ok(path.clone())));
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text,
+            "frame,simulation_time_s,frame_time_ms\n0,0.020,16.000\n1,0.040,17.000\n"
+        );
+        assert_eq!(FrameStats::default().finish_csv(), None);
+    }
+}
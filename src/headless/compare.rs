@@ -0,0 +1,231 @@
+use image::{Rgba, RgbaImage};
+
+use super::*;
+
+/// Depth error in world units that shows as the hottest color of the heatmap.
+const HEATMAP_DEPTH_RANGE: f32 = 1.0;
+/// Side and stride of the windows the ssim is averaged over.
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STRIDE: u32 = 4;
+
+/// What a technique rendered of the scene.
+pub struct Render {
+    pub color: RgbaImage,
+    pub depth: Vec<Option<f32>>,
+}
+
+/// How far apart the renders of two techniques are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// Over the rgb channels, infinite when the colors are equal.
+    pub psnr: f32,
+    /// Of the luma, 1 when the colors are equal.
+    pub ssim: f32,
+    /// In world units, over the pixels both techniques drew.
+    pub max_depth_error: f32,
+    pub mean_depth_error: f32,
+    /// Pixels only one of the techniques drew.
+    pub coverage_mismatches: usize,
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "psnr: {:.2} dB", self.psnr)?;
+        writeln!(f, "ssim: {:.4}", self.ssim)?;
+        writeln!(f, "max depth error: {:.4}", self.max_depth_error)?;
+        writeln!(f, "mean depth error: {:.4}", self.mean_depth_error)?;
+        write!(
+            f,
+            "coverage mismatches: {} pixels",
+            self.coverage_mismatches
+        )
+    }
+}
+
+impl Comparison {
+    pub fn new(a: &Render, b: &Render) -> Self {
+        assert_eq!(a.color.dimensions(), b.color.dimensions());
+
+        let channels = a.color.pixels().zip(b.color.pixels()).flat_map(|(a, b)| {
+            (0..3).map(move |channel| a.0[channel] as f32 - b.0[channel] as f32)
+        });
+        let (count, squared_sum) = channels.fold((0, 0.0), |(count, sum), error| {
+            (count + 1, sum + error * error)
+        });
+        let mse = squared_sum / count as f32;
+        let psnr = 10.0 * (255.0 * 255.0 / mse).log10();
+
+        let mut max_depth_error: f32 = 0.0;
+        let mut depth_error_sum = 0.0;
+        let mut depth_count = 0;
+        let mut coverage_mismatches = 0;
+        for (a, b) in a.depth.iter().zip(&b.depth) {
+            match (a, b) {
+                (Some(a), Some(b)) => {
+                    let error = (a - b).abs();
+                    max_depth_error = max_depth_error.max(error);
+                    depth_error_sum += error;
+                    depth_count += 1;
+                }
+                (None, None) => {}
+                _ => coverage_mismatches += 1,
+            }
+        }
+
+        Self {
+            psnr,
+            ssim: ssim(&a.color, &b.color),
+            max_depth_error,
+            mean_depth_error: depth_error_sum / depth_count.max(1) as f32,
+            coverage_mismatches,
+        }
+    }
+}
+
+fn luma(pixel: &Rgba<u8>) -> f32 {
+    let [r, g, b, _] = pixel.0.map(|c| c as f32);
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// The mean structural similarity of the luma over overlapping windows.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    let c1 = (0.01f32 * 255.0).powi(2);
+    let c2 = (0.03f32 * 255.0).powi(2);
+    let (width, height) = a.dimensions();
+    let mut sum = 0.0;
+    let mut windows = 0;
+    for y in (0..=height.saturating_sub(SSIM_WINDOW)).step_by(SSIM_STRIDE as usize) {
+        for x in (0..=width.saturating_sub(SSIM_WINDOW)).step_by(SSIM_STRIDE as usize) {
+            let pixels = (y..(y + SSIM_WINDOW).min(height))
+                .flat_map(|y| (x..(x + SSIM_WINDOW).min(width)).map(move |x| (x, y)))
+                .map(|(x, y)| (luma(a.get_pixel(x, y)), luma(b.get_pixel(x, y))));
+            let (mut n, mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+            for (a, b) in pixels {
+                n += 1.0;
+                sum_a += a;
+                sum_b += b;
+                sum_aa += a * a;
+                sum_bb += b * b;
+                sum_ab += a * b;
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let variance_a = sum_aa / n - mean_a * mean_a;
+            let variance_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            sum += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (variance_a + variance_b + c2));
+            windows += 1;
+        }
+    }
+    sum / windows as f32
+}
+
+/// The larger of the color and depth error of each pixel, from black through
+/// red and yellow to white, the pixels only one technique drew are white.
+pub fn mismatch_heatmap(a: &Render, b: &Render) -> RgbaImage {
+    let (width, height) = a.color.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let index = (y * width + x) as usize;
+        let color_error = a
+            .color
+            .get_pixel(x, y)
+            .0
+            .iter()
+            .zip(b.color.get_pixel(x, y).0)
+            .take(3)
+            .map(|(a, b)| a.abs_diff(b))
+            .max()
+            .unwrap_or(0) as f32
+            / 255.0;
+        let depth_error = match (a.depth[index], b.depth[index]) {
+            (Some(a), Some(b)) => (a - b).abs() / HEATMAP_DEPTH_RANGE,
+            (None, None) => 0.0,
+            _ => 1.0,
+        };
+        let heat = color_error.max(depth_error).clamp(0.0, 1.0);
+        let channel = |offset: f32| ((heat * 3.0 - offset).clamp(0.0, 1.0) * 255.0) as u8;
+        Rgba([channel(0.0), channel(1.0), channel(2.0), 255])
+    })
+}
+
+/// Renders the scene through two techniques with the same shading passes,
+/// reports how far apart they are and writes the mismatch heatmap.
+pub async fn run_compare(options: &HeadlessOptions, techniques: &[String; 2]) {
+    let shading = match &options.pipelines {
+        Some(names) => names.clone(),
+        None => SHADING_PIPELINES
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+    let mut renders = vec![];
+    // a renderer each, so that no history carries over between the techniques
+    for technique in techniques {
+        let Some(mut render_state) = RenderState::new_headless(
+            options.width,
+            options.height,
+            options.force_fallback_adapter,
+        )
+        .await
+        else {
+            error!("no adapter for headless rendering");
+            std::process::exit(1);
+        };
+        render_state.set_reverse_z(options.reverse_z);
+        let mut names = vec![technique.clone()];
+        names.extend(shading.iter().cloned());
+        if let Err(err) = render_state.run_only(&names) {
+            error!("{err}");
+            std::process::exit(2);
+        }
+        render_state.render_scene(options.camera, options.time_of_day_hours, options.frames);
+        renders.push(Render {
+            color: render_state.read_output(),
+            depth: render_state.read_view_depth(),
+        });
+    }
+
+    let comparison = Comparison::new(&renders[0], &renders[1]);
+    println!("{} and {}", techniques[0], techniques[1]);
+    println!("{comparison}");
+    match mismatch_heatmap(&renders[0], &renders[1]).save(&options.output) {
+        Ok(()) => info!("wrote {:?}", options.output),
+        Err(err) => error!("can't write {:?}: {err}", options.output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(offset: u32) -> Render {
+        let color = RgbaImage::from_fn(32, 32, |x, y| {
+            let value = if ((x + offset) / 4 + y / 4).is_multiple_of(2) {
+                200
+            } else {
+                20
+            };
+            Rgba([value, value, value, 255])
+        });
+        let depth = (0..32 * 32)
+            .map(|index| (index % 32 >= offset).then_some(10.0 + offset as f32))
+            .collect();
+        Render { color, depth }
+    }
+
+    #[test]
+    fn metrics() {
+        let same = Comparison::new(&checkerboard(0), &checkerboard(0));
+        assert_eq!(same.psnr, f32::INFINITY);
+        assert!((same.ssim - 1.0).abs() < 1e-4);
+        assert_eq!(same.max_depth_error, 0.0);
+        assert_eq!(same.coverage_mismatches, 0);
+
+        let shifted = Comparison::new(&checkerboard(0), &checkerboard(2));
+        assert!(shifted.psnr < 10.0);
+        assert!(shifted.ssim < 0.5);
+        assert_eq!(shifted.max_depth_error, 2.0);
+        assert_eq!(shifted.coverage_mismatches, 2 * 32);
+    }
+}
@@ -0,0 +1,183 @@
+//! Renders the simple universe through each technique and compares the color
+//! and depth against the reference images in `tests/golden`.
+//! Run with `GOLDEN_UPDATE=1` to write the references again after an
+//! intended change, the renders and diffs of failures go to `target/golden`.
+//! They need the fallback adapter of wgpu, a software renderer, and fail without it.
+
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use super::*;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 144;
+const TIME_OF_DAY_HOURS: f32 = 10.0;
+// enough for the hierarchy feedback to request and upload its chunks
+const FRAMES: u32 = 4;
+
+/// Largest difference of a color channel that still counts as equal,
+/// 12 steps of 8 bit in the 16 bit units of the comparison.
+const COLOR_TOLERANCE: u16 = 12 * 257;
+/// Largest difference of the linearized depth, 16 counts are about 0.25 units.
+const DEPTH_TOLERANCE: u16 = 16;
+/// Fraction of the pixels allowed over the tolerance, for the edges that
+/// rasterize differently on other adapters.
+const MAX_MISMATCHED_FRACTION: f32 = 0.002;
+
+const POSES: &[(&str, CameraPose)] = &[
+    (
+        "front",
+        CameraPose {
+            position: Vec3::new(16.0, 16.0, 56.0),
+            yaw: 0.0,
+            pitch: 0.0,
+        },
+    ),
+    (
+        "side",
+        CameraPose {
+            position: Vec3::new(56.0, 16.0, 16.0),
+            yaw: 90.0,
+            pitch: 0.0,
+        },
+    ),
+    (
+        "above",
+        CameraPose {
+            position: Vec3::new(40.0, 40.0, 40.0),
+            yaw: 45.0,
+            pitch: -35.0,
+        },
+    ),
+    // grazing the surface, where the dda takes the most steps
+    (
+        "close",
+        CameraPose {
+            position: Vec3::new(30.0, 20.0, 34.0),
+            yaw: 0.0,
+            pitch: -10.0,
+        },
+    ),
+];
+
+fn golden_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn updating() -> bool {
+    std::env::var_os("GOLDEN_UPDATE").is_some()
+}
+
+/// Counts the pixels with a channel over the tolerance and marks them red
+/// in the diff image, on top of the expected image darkened.
+fn compare(actual: &DynamicImage, expected: &DynamicImage, tolerance: u16) -> (usize, RgbaImage) {
+    let (actual, expected) = (actual.to_rgba16(), expected.to_rgba16());
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut mismatched = 0;
+    for (x, y, Rgba(expected_pixel)) in expected.enumerate_pixels() {
+        let Rgba(actual_pixel) = actual.get_pixel(x, y);
+        let over = expected_pixel
+            .iter()
+            .zip(actual_pixel)
+            .any(|(e, a)| e.abs_diff(*a) > tolerance);
+        let pixel = if over {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected_pixel.map(|c| (c >> 10) as u8);
+            Rgba([r, g, b, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+    (mismatched, diff)
+}
+
+/// Compares a render against its reference, or writes the reference when updating.
+/// The tolerance is in 16 bit units for both 8 and 16 bit images.
+fn check(name: &str, actual: DynamicImage, tolerance: u16) -> Option<String> {
+    let reference = golden_directory().join(format!("{name}.png"));
+    if updating() {
+        std::fs::create_dir_all(golden_directory()).unwrap();
+        actual.save(&reference).unwrap();
+        return None;
+    }
+
+    let output = output_directory();
+    std::fs::create_dir_all(&output).unwrap();
+    let actual_path = output.join(format!("{name}.png"));
+    actual.save(&actual_path).unwrap();
+
+    let expected = match image::open(&reference) {
+        Ok(expected) => expected,
+        Err(err) => {
+            return Some(format!(
+                "{name}: can't open {reference:?}: {err}, run with GOLDEN_UPDATE=1 to write it"
+            ))
+        }
+    };
+    if expected.dimensions() != actual.dimensions() || expected.color() != actual.color() {
+        return Some(format!(
+            "{name}: the reference is {:?} {:?}, the render is {:?} {:?}",
+            expected.dimensions(),
+            expected.color(),
+            actual.dimensions(),
+            actual.color()
+        ));
+    }
+
+    let (mismatched, diff) = compare(&actual, &expected, tolerance);
+    let allowed = (MAX_MISMATCHED_FRACTION * (WIDTH * HEIGHT) as f32) as usize;
+    if mismatched <= allowed {
+        return None;
+    }
+    let diff_path = output.join(format!("{name}-diff.png"));
+    diff.save(&diff_path).unwrap();
+    Some(format!(
+        "{name}: {mismatched} pixels differ from the reference, {allowed} allowed, \
+        see {actual_path:?} and {diff_path:?}"
+    ))
+}
+
+/// Renders every pose through a technique and the shading passes.
+fn check_technique(technique: &str) {
+    let Some(mut render_state) = pollster::block_on(RenderState::new_headless(WIDTH, HEIGHT, true))
+    else {
+        // a skip would report as a pass without rendering anything
+        panic!("no fallback adapter to render the golden images of {technique}");
+    };
+    let mut names = vec![technique.to_string()];
+    names.extend(SHADING_PIPELINES.iter().map(|name| name.to_string()));
+    render_state.run_only(&names).unwrap();
+
+    let prefix = technique.to_lowercase().replace(' ', "_");
+    let mut errors = vec![];
+    for (pose_name, pose) in POSES {
+        render_state.render_scene(*pose, TIME_OF_DAY_HOURS, FRAMES);
+        let name = format!("{prefix}_{pose_name}");
+        let color = DynamicImage::ImageRgba8(render_state.read_output());
+        errors.extend(check(&name, color, COLOR_TOLERANCE));
+        let depth = DynamicImage::ImageLuma16(render_state.read_depth_image());
+        errors.extend(check(&format!("{name}_depth"), depth, DEPTH_TOLERANCE));
+    }
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+}
+
+#[test]
+fn raycast_grid_plain_matches_golden() {
+    check_technique(raycast_grid_plain::PIPELINE_NAME);
+}
+
+#[test]
+fn raycast_hierarchy_feedback_matches_golden() {
+    check_technique(raycast_hierarchy_feedback::PIPELINE_NAME);
+}
+
+#[test]
+fn rasterize_instanced_matches_golden() {
+    check_technique(rasterize_instanced::PIPELINE_NAME);
+}
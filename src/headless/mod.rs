@@ -0,0 +1,537 @@
+use std::path::PathBuf;
+
+use crate::*;
+
+mod compare;
+#[cfg(test)]
+mod golden;
+
+pub use compare::*;
+
+/// Format of the offscreen "surface" attachment, read back as 8 bit srgb.
+pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// The passes that turn the gbuffer of a technique into the final image,
+/// the taa resolve jitters the camera and the debug ui changes with the timings.
+pub const SHADING_PIPELINES: &[&str] = &[
+    deferred_lighting::PIPELINE_NAME,
+    sky::PIPELINE_NAME,
+    post_process::PIPELINE_NAME,
+];
+
+const USAGE: &str = "usage: shader-practice --headless
+    [--size 640x360]
+    [--camera x,y,z,yaw,pitch]
+    [--time-of-day hours]
+    [--pipelines \"Name,Name\"]
+    [--frames n]
+    [--view-mode steps]
+    [--camera-mode isometric]
+    [--zoom height]
+    [--camera-path path.csv]
+    [--no-reverse-z]
+    [--fallback-adapter]
+    [--output out.png]
+    [--depth depth.png]
+    [--compare \"Technique,Technique\"]";
+
+/// Where the camera stands, yaw and pitch in degrees like the mouse look.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl CameraPose {
+    pub fn rotation(&self) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, self.yaw.to_radians())
+            * Quat::from_axis_angle(Vec3::X, self.pitch.to_radians())
+    }
+
+    pub fn from_sim_state(sim_state: &SimulationState) -> Self {
+        let (yaw, pitch, _) = sim_state.camera_rotation.to_euler(EulerRot::YXZ);
+        Self {
+            position: sim_state.camera_position,
+            yaw: yaw.to_degrees(),
+            pitch: pitch.to_degrees(),
+        }
+    }
+
+    pub fn apply(&self, sim_state: &mut SimulationState) {
+        sim_state.camera_position = self.position;
+        sim_state.camera_rotation = self.rotation();
+    }
+}
+
+// in the format of --camera
+impl std::fmt::Display for CameraPose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Vec3 { x, y, z } = self.position;
+        // adding zero turns -0 into 0
+        let (yaw, pitch) = (self.yaw + 0.0, self.pitch + 0.0);
+        write!(f, "{x:.2},{y:.2},{z:.2},{yaw:.1},{pitch:.1}")
+    }
+}
+
+impl std::str::FromStr for CameraPose {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers: Vec<f32> = s
+            .split(',')
+            .map(|n| n.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("not numbers: {s}"))?;
+        let [x, y, z, yaw, pitch] = numbers[..] else {
+            return Err(format!("not x,y,z,yaw,pitch: {s}"));
+        };
+        Ok(Self {
+            position: Vec3::new(x, y, z),
+            yaw,
+            pitch,
+        })
+    }
+}
+
+impl Default for CameraPose {
+    // in front of the sphere of the simple universe
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(16.0, 16.0, 56.0),
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+/// What to render without a window.
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    pub camera: CameraPose,
+    pub time_of_day_hours: f32,
+    /// The names of the pipelines to run, all of them when missing.
+    pub pipelines: Option<Vec<String>>,
+    /// What the voxel raycasters output.
+    pub view_mode: ViewMode,
+    /// The isometric mode ignores the yaw and pitch of the camera.
+    pub camera_mode: CameraMode,
+    /// The view height in world units of the orthographic modes.
+    pub zoom: f32,
+    /// Plays a recorded camera path at the simulation timestep, a frame a
+    /// step, instead of rendering the frames from the camera.
+    pub camera_path: Option<PathBuf>,
+    /// The depth convention of the projection.
+    pub reverse_z: bool,
+    /// Frames rendered before reading back, the temporal passes need a few to converge.
+    pub frames: u32,
+    pub force_fallback_adapter: bool,
+    pub output: PathBuf,
+    pub depth_output: Option<PathBuf>,
+    /// Two techniques to render with the same passes and compare,
+    /// the output is the mismatch heatmap then.
+    pub compare: Option<[String; 2]>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 360,
+            camera: CameraPose::default(),
+            time_of_day_hours: 10.0,
+            pipelines: None,
+            view_mode: ViewMode::default(),
+            camera_mode: CameraMode::default(),
+            zoom: SimulationState::new().camera_zoom,
+            camera_path: None,
+            reverse_z: Projection::default().reverse_z,
+            frames: 1,
+            force_fallback_adapter: false,
+            output: PathBuf::from("headless.png"),
+            depth_output: None,
+            compare: None,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    /// Returns None when the arguments don't ask for headless rendering.
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let args: Vec<String> = args.collect();
+        if !args.iter().any(|arg| arg == "--headless") {
+            return Ok(None);
+        }
+
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value\n{USAGE}"));
+            let invalid = |value: &str| format!("invalid value for {arg}: {value}\n{USAGE}");
+            match arg.as_str() {
+                "--headless" => {}
+                "--fallback-adapter" => options.force_fallback_adapter = true,
+                "--no-reverse-z" => options.reverse_z = false,
+                "--size" => {
+                    let value = value()?;
+                    let size = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|(w, h)| *w > 0 && *h > 0);
+                    let Some((width, height)) = size else {
+                        return Err(invalid(&value));
+                    };
+                    options.width = width;
+                    options.height = height;
+                }
+                "--camera" => {
+                    let value = value()?;
+                    options.camera = value.parse().map_err(|_| invalid(&value))?;
+                }
+                "--time-of-day" => {
+                    let value = value()?;
+                    options.time_of_day_hours = value.parse().map_err(|_| invalid(&value))?;
+                }
+                "--pipelines" => {
+                    let names = value()?.split(',').map(|n| n.trim().to_string()).collect();
+                    options.pipelines = Some(names);
+                }
+                "--view-mode" => {
+                    let value = value()?;
+                    options.view_mode = ViewMode::from_label(&value).ok_or(invalid(&value))?;
+                }
+                "--camera-mode" => {
+                    let value = value()?;
+                    options.camera_mode = CameraMode::from_label(&value).ok_or(invalid(&value))?;
+                }
+                "--zoom" => {
+                    let value = value()?;
+                    options.zoom = value
+                        .parse()
+                        .ok()
+                        .filter(|zoom: &f32| *zoom > 0.0)
+                        .ok_or(invalid(&value))?;
+                }
+                "--camera-path" => options.camera_path = Some(value()?.into()),
+                "--frames" => {
+                    let value = value()?;
+                    options.frames = value
+                        .parse()
+                        .ok()
+                        .filter(|frames| *frames > 0)
+                        .ok_or(invalid(&value))?;
+                }
+                "--output" => options.output = value()?.into(),
+                "--depth" => options.depth_output = Some(value()?.into()),
+                "--compare" => {
+                    let value = value()?;
+                    let Some((a, b)) = value.split_once(',') else {
+                        return Err(invalid(&value));
+                    };
+                    options.compare = Some([a.trim().to_string(), b.trim().to_string()]);
+                }
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+pub async fn run_headless(options: HeadlessOptions) {
+    env_logger::init();
+
+    if let Some(techniques) = &options.compare {
+        run_compare(&options, techniques).await;
+        return;
+    }
+
+    let Some(mut render_state) = RenderState::new_headless(
+        options.width,
+        options.height,
+        options.force_fallback_adapter,
+    )
+    .await
+    else {
+        error!("no adapter for headless rendering");
+        std::process::exit(1);
+    };
+    render_state.set_reverse_z(options.reverse_z);
+    if let Some(names) = &options.pipelines {
+        if let Err(err) = render_state.run_only(names) {
+            error!("{err}");
+            std::process::exit(2);
+        }
+    }
+    for pipeline in render_state.pipelines.iter_mut() {
+        pipeline.set_view_mode(options.view_mode);
+    }
+
+    let mut sim_state = scene_state(options.camera, options.time_of_day_hours);
+    sim_state.set_camera_mode(options.camera_mode);
+    sim_state.camera_zoom = options.zoom;
+    match &options.camera_path {
+        Some(file) => {
+            let path = match CameraPath::load(file) {
+                Ok(path) => path,
+                Err(err) => {
+                    error!("can't read {file:?}: {err}");
+                    std::process::exit(2);
+                }
+            };
+            sim_state.camera_path = Some(CameraPathState::Playing { path, time: 0.0 });
+            let mut input_state = InputState::new();
+            let mut frames = 0;
+            while sim_state.is_playing_camera_path() {
+                sim_state.update(SIMULATION_TIMESTEP, &mut input_state);
+                render_state.render_frames(&mut sim_state, 1);
+                frames += 1;
+            }
+            info!("played {frames} frames of {file:?}");
+        }
+        None => render_state.render_frames(&mut sim_state, options.frames),
+    }
+    render_state.profiler.flush(&render_state.device);
+    render_state.profiler.log();
+
+    let image = render_state.read_output();
+    match image.save(&options.output) {
+        Ok(()) => info!("wrote {:?}", options.output),
+        Err(err) => error!("can't write {:?}: {err}", options.output),
+    }
+    if let Some(depth_output) = &options.depth_output {
+        match render_state.read_depth_image().save(depth_output) {
+            Ok(()) => info!("wrote {depth_output:?}"),
+            Err(err) => error!("can't write {depth_output:?}: {err}"),
+        }
+    }
+}
+
+/// The simple universe seen from a camera pose at a fixed time of day.
+pub fn scene_state(camera: CameraPose, time_of_day_hours: f32) -> SimulationState {
+    let mut sim_state = SimulationState::new();
+    camera.apply(&mut sim_state);
+    sim_state.clock.time_of_day = time_of_day_hours / 24.0;
+    sim_state
+}
+
+impl RenderState<'static> {
+    /// Renders into offscreen textures on any adapter, without a window.
+    /// Returns None when there is no adapter, or no fallback adapter if forced.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        force_fallback_adapter: bool,
+    ) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await?;
+        info!("headless adapter: {:?}", adapter.get_info());
+        let (device, queue) = request_device(&adapter).await;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: HEADLESS_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            desired_maximum_frame_latency: 2,
+            view_formats: vec![],
+        };
+        let mut render_state = Self::with_device(device, queue, config, None, None);
+        render_state.resize(winit::dpi::PhysicalSize::new(width, height));
+        Some(render_state)
+    }
+}
+
+impl RenderState<'_> {
+    /// Skips every pipeline that is not named, fails on the names that don't exist.
+    pub fn run_only(&mut self, names: &[String]) -> Result<(), String> {
+        let available: Vec<String> = self.pipelines.iter().map(|p| p.get_name()).collect();
+        if let Some(unknown) = names.iter().find(|name| !available.contains(name)) {
+            return Err(format!(
+                "unknown pipeline {unknown:?}, the pipelines are {available:?}"
+            ));
+        }
+        for pipeline in self.pipelines.iter_mut() {
+            pipeline.set_skip(!names.contains(&pipeline.get_name()));
+        }
+        Ok(())
+    }
+
+    /// Renders the simple universe from a camera pose at a fixed time of day.
+    pub fn render_scene(&mut self, camera: CameraPose, time_of_day_hours: f32, frames: u32) {
+        self.render_frames(&mut scene_state(camera, time_of_day_hours), frames);
+    }
+
+    pub fn render_frames(&mut self, sim_state: &mut SimulationState, frames: u32) {
+        for _ in 0..frames {
+            self.extract(sim_state);
+            self.render()
+                .expect("the headless renderer has no surface to lose");
+        }
+    }
+
+    /// Reads back what the last frame presented, only without a window.
+    pub fn read_output(&self) -> image::RgbaImage {
+        let Some(Attachment::Color(ColorAttachment {
+            texture: Some(texture),
+            ..
+        })) = self.attachments.get("surface")
+        else {
+            panic!("only the headless renderer can read its output");
+        };
+        let data = read_texture(&self.device, &self.queue, texture, wgpu::TextureAspect::All);
+        image::RgbaImage::from_raw(texture.width(), texture.height(), data)
+            .expect("the readback has the size of the texture")
+    }
+
+    /// Reads back the depth buffer of the last frame.
+    pub fn read_depth(&self) -> Vec<f32> {
+        let Some(depth_bind_group) = self.bind_groups.get("depth") else {
+            panic!("depth bind group missing");
+        };
+
+        // not every backend can copy a depth texture to a buffer,
+        // draw the depth into a float texture and copy that instead
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Copy Texture"),
+            size: wgpu::Extent3d {
+                width: self.config.width,
+                height: self.config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let shader = include_shader!(&self.device, "depth_copy.wgsl");
+        let pipeline_layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth Copy Render Pipeline Layout"),
+                bind_group_layouts: &[&depth_bind_group.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Depth Copy Render Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::R32Float,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Depth Copy Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Copy Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &depth_bind_group.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        let data = read_texture(
+            &self.device,
+            &self.queue,
+            &texture,
+            wgpu::TextureAspect::All,
+        );
+        bytemuck::cast_slice(&data).to_vec()
+    }
+
+    /// The distance along the view direction of each pixel of the depth buffer,
+    /// None where nothing was drawn.
+    pub fn read_view_depth(&self) -> Vec<Option<f32>> {
+        let view_from_clip = self.unjittered_clip_from_view.inverse();
+        let far_depth = self.projection.far_depth();
+        self.read_depth()
+            .into_iter()
+            .map(|depth| {
+                if depth == far_depth {
+                    return None;
+                }
+                let view = view_from_clip * Vec4::new(0.0, 0.0, depth, 1.0);
+                Some(-view.z / view.w)
+            })
+            .collect()
+    }
+
+    /// The depth buffer linearized up to the far plane like the debug depth
+    /// view, black at the camera and white where nothing was drawn.
+    pub fn read_depth_image(&self) -> image::ImageBuffer<image::Luma<u16>, Vec<u16>> {
+        let pixels = self
+            .read_view_depth()
+            .into_iter()
+            .map(|view_depth| {
+                let linear = view_depth.map_or(1.0, |z| self.projection.display_depth(z));
+                (linear * u16::MAX as f32) as u16
+            })
+            .collect();
+        image::ImageBuffer::from_raw(self.config.width, self.config.height, pixels)
+            .expect("the readback has the size of the texture")
+    }
+}
+
+/// Copies a texture to the cpu and waits for it, with the rows tightly packed.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    aspect: wgpu::TextureAspect,
+) -> Vec<u8> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    let readback = Readback::copy(device, &mut encoder, texture, aspect);
+    queue.submit(Some(encoder.finish()));
+    readback.map(Default::default());
+    device.poll(wgpu::Maintain::Wait);
+    readback.data()
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     f32::consts::PI,
+    rc::Rc,
     time::{Duration, Instant},
 };
 
@@ -18,27 +19,102 @@ use winit::{
 };
 
 mod attachments;
+mod camera_path;
+mod capture;
+mod frame_stats;
+mod headless;
+mod profiler;
+mod projection;
+mod reflection;
+mod shaders;
+mod view_mode;
 mod voxels;
 
 mod analytical_sdf_cube;
 mod analytical_sdf_sphere;
 mod debug_depth;
+mod debug_draw;
 mod debug_empty;
+mod debug_residency;
 mod debug_ui;
+mod deferred_lighting;
+mod post_process;
+mod present;
 mod rasterize_instanced;
 mod rasterize_simple;
 mod raycast_grid_plain;
 mod raycast_hierarchy_feedback;
 mod raycast_sdf;
+mod sky;
+mod taa_resolve;
 
 use attachments::*;
+use camera_path::*;
+use capture::*;
+use debug_draw::DebugLines;
+use frame_stats::*;
+use headless::*;
+use profiler::*;
+use projection::*;
+use raycast_hierarchy_feedback::ChunkResidency;
+use reflection::*;
+use shaders::*;
+use view_mode::*;
 use voxels::*;
 
+/// The step of the simulation, it runs as many of them as the frame took.
+const SIMULATION_TIMESTEP: Duration = Duration::from_millis(20);
+
+/// The keys that toggle the first pipelines, and their labels in the debug ui.
+const PIPELINE_KEYS: [(KeyCode, &str); 10] = [
+    (KeyCode::Digit1, "1"),
+    (KeyCode::Digit2, "2"),
+    (KeyCode::Digit3, "3"),
+    (KeyCode::Digit4, "4"),
+    (KeyCode::Digit5, "5"),
+    (KeyCode::Digit6, "6"),
+    (KeyCode::Digit7, "7"),
+    (KeyCode::Digit8, "8"),
+    (KeyCode::Digit9, "9"),
+    (KeyCode::Digit0, "0"),
+];
+
+/// What the simulation and the pipelines hand to the later pipelines while
+/// extracting a frame, made anew every frame.
+#[derive(Debug, Default)]
+pub struct FrameData {
+    /// Uploaded by the debug draw pipeline, lines drawn by the pipelines after
+    /// it are left out.
+    pub lines: DebugLines,
+    /// The chunks of the hierarchy raycast after its extract, for the overlays.
+    pub residency: Option<ChunkResidency>,
+}
+
+/// The parts of a frame, each one reads what the ones before it wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineStage {
+    /// Draws the scene with the depth test, in any order.
+    Scene,
+    /// Reads the attachments of the pipelines before it, in a fixed order.
+    Shading,
+    /// Draws over the frame, in any order.
+    Overlay,
+}
+
+impl PipelineStage {
+    pub fn is_reorderable(&self) -> bool {
+        *self != PipelineStage::Shading
+    }
+}
+
 pub trait PipelineState {
+    /// The depth test and the depth cleared to come from the projection, the
+    /// pipelines are built again when its depth convention changes.
     fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self
     where
         Self: Sized;
@@ -46,12 +122,50 @@ pub trait PipelineState {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         clear_depth: bool,
     );
 
-    fn extract(&mut self, _sim_state: &mut SimulationState, queue: &wgpu::Queue) {}
+    /// Renders inside a profiler scope named after the pipeline, pipelines
+    /// with more than one pass can time them separately.
+    fn render_profiled(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        clear_depth: bool,
+        profiler: &mut Profiler,
+    ) {
+        profiler.scope(encoder, &self.get_name(), |encoder| {
+            self.render(encoder, bind_groups, attachments, clear_depth)
+        });
+    }
+
+    fn extract(
+        &mut self,
+        _sim_state: &mut SimulationState,
+        _frame: &mut FrameData,
+        _queue: &wgpu::Queue,
+    ) {
+    }
+
+    // pipelines that support deferred shading write to the "gbuffer" attachment
+    // instead of the "color" attachment when deferred is set
+    fn set_deferred(&mut self, _deferred: bool) {}
+
+    // the voxel raycasters can output a debug view instead of the shaded scene,
+    // the other pipelines have no view mode
+    fn get_view_mode(&self) -> Option<ViewMode> {
+        None
+    }
+    fn set_view_mode(&mut self, _view_mode: ViewMode) {}
+
+    // where the pipeline runs in the frame, only the pipelines of a stage
+    // that doesn't depend on its order can trade places in the panel
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Shading
+    }
 
     fn get_skip(&self) -> bool;
     fn set_skip(&mut self, skip: bool);
@@ -70,13 +184,232 @@ struct GlobalUniform {
     clip_from_view: Mat4,
     view_from_world: Mat4,
     world_from_view: Mat4,
+    // the view-projection of the previous frame, for reprojection
+    prev_clip_from_world: Mat4,
+    frame_index: u32,
+    // seconds of simulation since the start
+    time: f32,
+    // 1 when the rays are parallel, they start on the near plane
+    orthographic: u32,
+    _padding: u32,
+    // sub-pixel offset in ndc applied to clip_from_view this frame
+    jitter: Vec4,
+    // the projection of clip_from_view, the far plane is at infinity with reverse z
+    near: f32,
+    far: f32,
+    fov_y: f32,
+    reverse_z: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    sun_direction: Vec4,
+    moon_direction: Vec4,
+    // the sun during the day and the moon at night
+    light_direction: Vec4,
+    light_color: Vec4,
+    ambient_color: Vec4,
+    // orthographic projection of the sun used by the shadow map passes
+    light_from_world: Mat4,
+    // hemisphere rays per pixel traced by the raycasters for ao and gi
+    gi_ray_count: u32,
+    // extinction per unit of distance of the fog towards the sky color
+    fog_density: f32,
+    _padding: [u32; 2],
 }
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct UiUniform {
-    pipelines_skip: [[u32; 4]; 256],
-    pipelines_num: u32,
+    text: [[u32; 4]; debug_ui::TEXT_COLUMNS * debug_ui::TEXT_ROWS / 8],
+    font: [[u32; 4]; 48],
+    // set while a reloaded shader fails to compile
+    shader_error: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    exposure: f32,
+    // 0: clamp, 1: aces, 2: agx
+    tonemapper: u32,
+    // zero disables the bloom
+    bloom_intensity: f32,
+    bloom_threshold: f32,
+    lut_strength: f32,
+    vignette_strength: f32,
+    fxaa: u32,
+    _padding: u32,
+}
+
+impl Default for PostUniform {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapper: 1,
+            bloom_intensity: 0.05,
+            bloom_threshold: 1.0,
+            lut_strength: 1.0,
+            vignette_strength: 0.3,
+            fxaa: 1,
+            _padding: 0,
+        }
+    }
+}
+
+// the sizes of the uniforms are in the layouts so that wgpu checks
+// the shader structs fit in them when a pipeline is created
+const GLOBAL_BIND_GROUP_LAYOUT: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<GlobalUniform>() as u64),
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<LightingUniform>() as u64),
+        },
+        count: None,
+    },
+];
+
+const UI_BIND_GROUP_LAYOUT: &[wgpu::BindGroupLayoutEntry] = &[wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<UiUniform>() as u64),
+    },
+    count: None,
+}];
+
+const POST_BIND_GROUP_LAYOUT: &[wgpu::BindGroupLayoutEntry] = &[wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<PostUniform>() as u64),
+    },
+    count: None,
+}];
+
+/// The uniforms declared both here and in the shaders, every shader module
+/// is checked against them when it's created.
+fn host_structs() -> Vec<HostStruct> {
+    use debug_residency::{ResidencyUniform, RESIDENCY_BIND_GROUP_LAYOUT};
+    vec![
+        host_struct!(
+            GlobalUniform {
+                viewport_size,
+                view_world_position,
+                world_from_clip,
+                clip_from_world,
+                view_from_clip,
+                clip_from_view,
+                view_from_world,
+                world_from_view,
+                prev_clip_from_world,
+                frame_index,
+                time,
+                orthographic,
+                _padding,
+                jitter,
+                near,
+                far,
+                fov_y,
+                reverse_z,
+            },
+            GLOBAL_BIND_GROUP_LAYOUT,
+            0
+        ),
+        host_struct!(
+            LightingUniform {
+                sun_direction,
+                moon_direction,
+                light_direction,
+                light_color,
+                ambient_color,
+                light_from_world,
+                gi_ray_count,
+                fog_density,
+                _padding,
+            },
+            GLOBAL_BIND_GROUP_LAYOUT,
+            1
+        ),
+        host_struct!(
+            UiUniform {
+                text,
+                font,
+                shader_error,
+                _padding,
+            },
+            UI_BIND_GROUP_LAYOUT,
+            0
+        ),
+        host_struct!(
+            PostUniform {
+                exposure,
+                tonemapper,
+                bloom_intensity,
+                bloom_threshold,
+                lut_strength,
+                vignette_strength,
+                fxaa,
+                _padding,
+            },
+            POST_BIND_GROUP_LAYOUT,
+            0
+        ),
+        host_struct!(ResidencyUniform { slots }, RESIDENCY_BIND_GROUP_LAYOUT, 0),
+        host_struct!(
+            ViewModeUniform {
+                view_mode,
+                max_steps,
+                depth_range,
+                distance_band,
+            },
+            VIEW_MODE_BIND_GROUP_LAYOUT,
+            VIEW_MODE_BINDING
+        ),
+    ]
+}
+
+type PipelineConstructor = fn(
+    &wgpu::Device,
+    &wgpu::SurfaceConfiguration,
+    &Projection,
+    &mut HashMap<String, Rc<BindGroupState>>,
+) -> Box<dyn PipelineState>;
+
+fn construct_pipeline<T: PipelineState + 'static>(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    projection: &Projection,
+    bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+) -> Box<dyn PipelineState> {
+    Box::new(T::new(device, config, projection, bind_groups))
+}
+
+// what is needed to rebuild a pipeline when its shaders change
+struct PipelineSource {
+    constructor: PipelineConstructor,
+    shaders: Vec<std::path::PathBuf>,
+    // the error of the last failed rebuild
+    error: Option<String>,
 }
 
 pub struct BindGroupState {
@@ -86,19 +419,42 @@ pub struct BindGroupState {
 }
 
 struct RenderState<'a> {
-    surface: wgpu::Surface<'a>,
+    // both missing when rendering headless
+    surface: Option<wgpu::Surface<'a>>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
-    window: &'a Window,
+    window: Option<&'a Window>,
     //
-    bind_groups: HashMap<String, BindGroupState>,
+    bind_groups: HashMap<String, Rc<BindGroupState>>,
     attachments: HashMap<String, Attachment>,
     pipelines: Vec<Box<dyn PipelineState>>,
+    pipeline_sources: Vec<PipelineSource>,
+    present: present::Pipeline,
+    // only in hot reload mode
+    shader_watcher: Option<ShaderWatcher>,
+    window_title: String,
     //
     uniform_global: GlobalUniform,
+    uniform_lighting: LightingUniform,
     uniform_ui: UiUniform,
+    uniform_post: PostUniform,
+    //
+    deferred: bool,
+    gi_ray_count: u32,
+    pub projection: Projection,
+    // the projection without jitter and the view-projection it gave last frame
+    unjittered_clip_from_view: Mat4,
+    unjittered_clip_from_world: Mat4,
+    // the camera frozen to look at what it culls from outside
+    frozen_world_from_clip: Option<Mat4>,
+    captures: Captures,
+    profiler: Profiler,
+    // for the fps of the debug ui
+    time_last_extract: Instant,
+    frame_time_average: f32,
+    debug_panel: debug_ui::DebugPanel,
 }
 
 impl<'a> RenderState<'a> {
@@ -121,21 +477,7 @@ impl<'a> RenderState<'a> {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await;
 
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps
@@ -145,7 +487,9 @@ impl<'a> RenderState<'a> {
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // copied for the captures where the surface allows it
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format: surface_format,
             width: size.width,
             height: size.height,
@@ -155,6 +499,19 @@ impl<'a> RenderState<'a> {
             view_formats: vec![],
         };
 
+        Self::with_device(device, queue, config, Some(surface), Some(window))
+    }
+
+    /// Creates the resources and the pipelines, without a surface the
+    /// pipelines present into an offscreen "surface" attachment.
+    fn with_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface<'a>>,
+        window: Option<&'a Window>,
+    ) -> RenderState<'a> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
         let mut bind_groups = HashMap::new();
 
         let uniform_global = GlobalUniform {
@@ -166,44 +523,72 @@ impl<'a> RenderState<'a> {
             clip_from_view: Mat4::ZERO,
             view_from_world: Mat4::ZERO,
             world_from_view: Mat4::ZERO,
+            prev_clip_from_world: Mat4::ZERO,
+            frame_index: 0,
+            time: 0.0,
+            orthographic: 0,
+            _padding: 0,
+            jitter: Vec4::ZERO,
+            near: 0.0,
+            far: 0.0,
+            fov_y: 0.0,
+            reverse_z: 0,
         };
         let global_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Global Buffer"),
             contents: bytemuck::cast_slice(&[uniform_global]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        // the lighting uniform shares the global bind group,
+        // pipelines already use up to the 4 bind groups allowed by default
+        let uniform_lighting = LightingUniform {
+            sun_direction: Vec4::ZERO,
+            moon_direction: Vec4::ZERO,
+            light_direction: Vec4::ZERO,
+            light_color: Vec4::ZERO,
+            ambient_color: Vec4::ZERO,
+            light_from_world: Mat4::ZERO,
+            gi_ray_count: 0,
+            fog_density: 0.0,
+            _padding: [0; 2],
+        };
+        let lighting_uniform_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Lighting Buffer"),
+                contents: bytemuck::cast_slice(&[uniform_lighting]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
         let global_uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: GLOBAL_BIND_GROUP_LAYOUT,
                 label: Some("global_bind_group_layout"),
             });
         let global_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &global_uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: global_uniform_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: global_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lighting_uniform_buffer.as_entire_binding(),
+                },
+            ],
             label: Some("global_bind_group"),
         });
         let global_bind_group = BindGroupState {
-            buffer: vec![global_uniform_buffer],
+            buffer: vec![global_uniform_buffer, lighting_uniform_buffer],
             bind_group: global_uniform_bind_group,
             bind_group_layout: global_uniform_bind_group_layout,
         };
-        bind_groups.insert("global".to_string(), global_bind_group);
+        bind_groups.insert("global".to_string(), Rc::new(global_bind_group));
 
         let uniform_ui = UiUniform {
-            pipelines_num: 1,
-            pipelines_skip: [[0; 4]; 256],
+            text: debug_ui::TextGrid::default().pack(),
+            font: debug_ui::packed_font(),
+            shader_error: 0,
+            _padding: [0; 3],
         };
         let ui_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Ui Buffer"),
@@ -212,16 +597,7 @@ impl<'a> RenderState<'a> {
         });
         let ui_uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: UI_BIND_GROUP_LAYOUT,
                 label: Some("ui_bind_group_layout"),
             });
         let ui_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -237,7 +613,33 @@ impl<'a> RenderState<'a> {
             bind_group: ui_uniform_bind_group,
             bind_group_layout: ui_uniform_bind_group_layout,
         };
-        bind_groups.insert("ui".to_string(), ui_bind_group);
+        bind_groups.insert("ui".to_string(), Rc::new(ui_bind_group));
+
+        let uniform_post = PostUniform::default();
+        let post_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Buffer"),
+            contents: bytemuck::cast_slice(&[uniform_post]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let post_uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: POST_BIND_GROUP_LAYOUT,
+                label: Some("post_bind_group_layout"),
+            });
+        let post_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &post_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: post_uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("post_bind_group"),
+        });
+        let post_bind_group = BindGroupState {
+            buffer: vec![post_uniform_buffer],
+            bind_group: post_uniform_bind_group,
+            bind_group_layout: post_uniform_bind_group_layout,
+        };
+        bind_groups.insert("post_settings".to_string(), Rc::new(post_bind_group));
 
         let diffuse_bytes = include_bytes!(".././assets/blocks.png");
         let diffuse_image = image::load_from_memory(diffuse_bytes).unwrap();
@@ -328,36 +730,46 @@ impl<'a> RenderState<'a> {
             bind_group: diffuse_bind_group,
             bind_group_layout: texture_bind_group_layout,
         };
-        bind_groups.insert("diffuse".to_string(), diffuse_bind_group);
+        bind_groups.insert("diffuse".to_string(), Rc::new(diffuse_bind_group));
 
         let mut attachments = HashMap::new();
-        attachments.insert(
-            "depth".to_string(),
-            Attachment::Depth(DepthAttachment::create_depth_texture(
-                &device,
-                &config,
-                &mut bind_groups,
-            )),
-        );
+        create_screen_attachments(&device, &config, &mut attachments, &mut bind_groups);
+
+        let projection = Projection::default();
 
         let mut pipelines: Vec<Box<dyn PipelineState>> = Vec::new();
+        let mut pipeline_sources: Vec<PipelineSource> = Vec::new();
 
         // Shorthand to construct the pipelines vec
         struct Params<'a> {
             pipelines: &'a mut Vec<Box<dyn PipelineState>>,
+            pipeline_sources: &'a mut Vec<PipelineSource>,
             device: &'a wgpu::Device,
             config: &'a wgpu::SurfaceConfiguration,
-            bind_groups: &'a mut HashMap<String, BindGroupState>,
+            projection: &'a Projection,
+            bind_groups: &'a mut HashMap<String, Rc<BindGroupState>>,
         }
         let mut p = Params {
             pipelines: &mut pipelines,
+            pipeline_sources: &mut pipeline_sources,
             device: &device,
             config: &config,
+            projection: &projection,
             bind_groups: &mut bind_groups,
         };
         fn push_pipeline<'a, T: PipelineState + 'static>(p: &'a mut Params) {
-            p.pipelines
-                .push(Box::new(T::new(p.device, p.config, p.bind_groups)))
+            let constructor: PipelineConstructor = construct_pipeline::<T>;
+            let (pipeline, record) =
+                record_shaders(|| constructor(p.device, p.config, p.projection, p.bind_groups));
+            if let Some(err) = record.errors.first() {
+                panic!("{}: {err}", pipeline.get_name());
+            }
+            p.pipelines.push(pipeline);
+            p.pipeline_sources.push(PipelineSource {
+                constructor,
+                shaders: record.files,
+                error: None,
+            });
         }
 
         // ┌─┐                                  ┌─┐ //
@@ -374,9 +786,18 @@ impl<'a> RenderState<'a> {
         push_pipeline::<raycast_grid_plain::Pipeline>(&mut p);
         push_pipeline::<raycast_hierarchy_feedback::Pipeline>(&mut p);
         push_pipeline::<rasterize_instanced::Pipeline>(&mut p);
+        push_pipeline::<deferred_lighting::Pipeline>(&mut p);
+        push_pipeline::<sky::Pipeline>(&mut p);
+        push_pipeline::<taa_resolve::Pipeline>(&mut p);
+        push_pipeline::<post_process::Pipeline>(&mut p);
+        push_pipeline::<debug_residency::Pipeline>(&mut p);
+        push_pipeline::<debug_draw::Pipeline>(&mut p);
         push_pipeline::<debug_depth::Pipeline>(&mut p);
         push_pipeline::<debug_ui::Pipeline>(&mut p);
 
+        let present = present::Pipeline::new(&device, &config, &projection, &mut bind_groups);
+        let profiler = Profiler::new(&device, &queue);
+
         Self {
             surface,
             device,
@@ -385,15 +806,32 @@ impl<'a> RenderState<'a> {
             size,
             window,
             uniform_global,
+            uniform_lighting,
             uniform_ui,
+            uniform_post,
             attachments,
             bind_groups,
             pipelines,
+            pipeline_sources,
+            present,
+            shader_watcher: hot_reload_enabled().then(ShaderWatcher::new),
+            window_title: window.map_or(String::new(), |window| window.title()),
+            deferred: false,
+            gi_ray_count: 2,
+            projection,
+            unjittered_clip_from_view: Mat4::IDENTITY,
+            unjittered_clip_from_world: Mat4::IDENTITY,
+            frozen_world_from_clip: None,
+            captures: Captures::default(),
+            profiler,
+            time_last_extract: Instant::now(),
+            frame_time_average: 0.0,
+            debug_panel: debug_ui::DebugPanel::default(),
         }
     }
 
     fn window(&self) -> &Window {
-        &self.window
+        self.window.expect("the headless renderer has no window")
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -401,35 +839,77 @@ impl<'a> RenderState<'a> {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
             self.uniform_global.viewport_size =
                 Vec4::new(new_size.width as f32, new_size.height as f32, 0.0, 0.0);
-            self.uniform_global.clip_from_view = Mat4::perspective_rh(
-                PI * 0.5,
-                self.uniform_global.viewport_size.x / self.uniform_global.viewport_size.y,
-                0.1,
-                1000.0,
-            );
-            self.uniform_global.view_from_clip = self.uniform_global.clip_from_view.inverse();
 
-            self.attachments.insert(
-                "depth".to_string(),
-                Attachment::Depth(DepthAttachment::create_depth_texture(
+            create_screen_attachments(
+                &self.device,
+                &self.config,
+                &mut self.attachments,
+                &mut self.bind_groups,
+            );
+            if self.surface.is_none() {
+                let output = ColorAttachment::create_texture(
                     &self.device,
-                    &self.config,
+                    self.config.width,
+                    self.config.height,
+                    self.config.format,
+                    "surface",
                     &mut self.bind_groups,
-                )),
-            );
+                );
+                self.attachments
+                    .insert("surface".to_string(), Attachment::Color(output));
+            }
         }
     }
 
     pub fn extract(&mut self, sim_state: &mut SimulationState) {
+        self.uniform_global.time = sim_state.clock.elapsed;
         self.uniform_global.view_world_position = sim_state.camera_position.extend(0.0);
         self.uniform_global.world_from_view =
             Mat4::from_rotation_translation(sim_state.camera_rotation, sim_state.camera_position);
         self.uniform_global.view_from_world = self.uniform_global.world_from_view.inverse();
 
-        // the view-projection matrix
+        self.projection.ortho_height = sim_state
+            .camera_mode
+            .is_orthographic()
+            .then_some(sim_state.camera_zoom);
+        let projection = self.projection;
+        self.uniform_global.orthographic = projection.ortho_height.is_some() as u32;
+        let aspect_ratio =
+            self.uniform_global.viewport_size.x / self.uniform_global.viewport_size.y;
+        self.unjittered_clip_from_view = projection.clip_from_view(aspect_ratio);
+        self.uniform_global.near = projection.near;
+        self.uniform_global.far = projection.far;
+        self.uniform_global.fov_y = projection.fov_y;
+        self.uniform_global.reverse_z = projection.reverse_z as u32;
+
+        // jitter the projection only while the taa resolve is there to converge it
+        self.uniform_global.frame_index = self.uniform_global.frame_index.wrapping_add(1);
+        let taa = self
+            .pipelines
+            .iter()
+            .any(|p| p.get_name() == taa_resolve::PIPELINE_NAME && !p.get_skip());
+        let jitter = if taa {
+            let index = self.uniform_global.frame_index % 8 + 1;
+            let offset = Vec2::new(halton(index, 2), halton(index, 3)) - 0.5;
+            offset * 2.0 / self.uniform_global.viewport_size.truncate().truncate()
+        } else {
+            Vec2::ZERO
+        };
+        self.uniform_global.jitter = jitter.extend(0.0).extend(0.0);
+        self.uniform_global.clip_from_view =
+            Mat4::from_translation(jitter.extend(0.0)) * self.unjittered_clip_from_view;
+        self.uniform_global.view_from_clip = self.uniform_global.clip_from_view.inverse();
+
+        // the view-projection matrix, the previous one is kept without jitter
+        // so that reprojecting lands on the resolved history
+        self.uniform_global.prev_clip_from_world = self.unjittered_clip_from_world;
+        self.unjittered_clip_from_world =
+            self.unjittered_clip_from_view * self.uniform_global.view_from_world;
         self.uniform_global.clip_from_world =
             self.uniform_global.clip_from_view * self.uniform_global.view_from_world;
         self.uniform_global.world_from_clip = self.uniform_global.clip_from_world.inverse();
@@ -448,14 +928,62 @@ impl<'a> RenderState<'a> {
             bytemuck::cast_slice(&[self.uniform_global]),
         );
 
-        self.uniform_ui.pipelines_num = self.pipelines.len() as u32;
-        for i in 0..self.pipelines.len() {
-            self.uniform_ui.pipelines_skip[i] = if self.pipelines[i].get_skip() {
-                [1, 0, 0, 0]
+        let sun_direction = sim_state.clock.sun_direction();
+        let moon_direction = sim_state.clock.moon_direction();
+        self.uniform_lighting.sun_direction = sun_direction.extend(0.0);
+        self.uniform_lighting.moon_direction = moon_direction.extend(0.0);
+        // the light fades out at the horizon before switching between the sun and the moon
+        let (light_direction, light_color) = if sun_direction.y >= 0.0 {
+            let sunset = Vec3::new(1.0, 0.55, 0.3);
+            let noon = Vec3::new(1.0, 0.97, 0.92);
+            let color = sunset.lerp(noon, smoothstep(0.0, 0.35, sun_direction.y));
+            (sun_direction, color * smoothstep(0.0, 0.1, sun_direction.y))
+        } else {
+            let color = Vec3::new(0.12, 0.14, 0.2);
+            (
+                moon_direction,
+                color * smoothstep(0.0, 0.1, moon_direction.y),
+            )
+        };
+        self.uniform_lighting.light_direction = light_direction.extend(0.0);
+        self.uniform_lighting.light_color = light_color.extend(1.0);
+        let night_ambient = Vec3::new(0.02, 0.025, 0.04);
+        let day_ambient = Vec3::new(0.22, 0.25, 0.3);
+        let daylight = smoothstep(-0.1, 0.2, sun_direction.y);
+        self.uniform_lighting.ambient_color = night_ambient.lerp(day_ambient, daylight).extend(1.0);
+        self.uniform_lighting.gi_ray_count = self.gi_ray_count;
+        self.uniform_lighting.fog_density = 0.01;
+        if let Some((min, max)) = sim_state.universe.bounds() {
+            // fit the light projection around the whole universe
+            let center = (min + max).as_vec3() * 0.5;
+            let radius = (max - min).as_vec3().length() * 0.5;
+            let up = if light_direction.abs_diff_eq(Vec3::Y, 0.01)
+                || light_direction.abs_diff_eq(-Vec3::Y, 0.01)
+            {
+                Vec3::Z
             } else {
-                [0, 0, 0, 0]
+                Vec3::Y
             };
+            let light_from_view =
+                Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+            let view_from_world =
+                Mat4::look_at_rh(center + light_direction * radius * 2.0, center, up);
+            self.uniform_lighting.light_from_world = light_from_view * view_from_world;
         }
+        let Some(lighting_buffer) = self.bind_groups.get("global").and_then(|b| b.buffer.get(1))
+        else {
+            return;
+        };
+        self.queue.write_buffer(
+            lighting_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform_lighting]),
+        );
+
+        let frame_time = self.time_last_extract.elapsed().as_secs_f32();
+        self.time_last_extract = Instant::now();
+        self.frame_time_average = self.frame_time_average * 0.95 + frame_time * 0.05;
+        self.uniform_ui.text = self.debug_layout(sim_state).0.pack();
 
         let Some(ui_buffer) = self
             .bind_groups
@@ -468,18 +996,182 @@ impl<'a> RenderState<'a> {
         self.queue
             .write_buffer(ui_buffer, 0, bytemuck::cast_slice(&[self.uniform_ui]));
 
+        let Some(post_buffer) = self
+            .bind_groups
+            .get("post_settings")
+            .and_then(|b| b.buffer.first())
+        else {
+            return;
+        };
+        self.queue
+            .write_buffer(post_buffer, 0, bytemuck::cast_slice(&[self.uniform_post]));
+
+        let mut frame = FrameData::default();
+        sim_state.draw_debug(&mut frame.lines);
+        if let Some(world_from_clip) = self.frozen_world_from_clip {
+            let color = Vec4::new(0.3, 0.8, 1.0, 1.0);
+            frame.lines.frustum(world_from_clip, color);
+            // the center of the near plane is next to the camera
+            let center = world_from_clip.project_point3(Vec3::ZERO);
+            frame.lines.sphere(center, 0.25, color);
+        }
         for pipeline in self.pipelines.iter_mut() {
-            pipeline.extract(sim_state, &self.queue);
+            pipeline.set_deferred(self.deferred);
+            pipeline.extract(sim_state, &mut frame, &self.queue);
+        }
+    }
+
+    /// Rebuilds the pipelines whose shaders changed on disk. A pipeline that
+    /// fails to build is discarded and the previous one stays in use.
+    fn reload_shaders(&mut self) {
+        let Some(shader_watcher) = self.shader_watcher.as_mut() else {
+            return;
+        };
+        let changed = shader_watcher.changed();
+        if changed.is_empty() {
+            return;
+        }
+
+        for i in 0..self.pipelines.len() {
+            let source = &self.pipeline_sources[i];
+            if source.shaders.iter().any(|shader| changed.contains(shader)) {
+                self.rebuild_pipeline(i);
+            }
+        }
+        self.show_pipeline_errors();
+    }
+
+    /// Builds a pipeline again from its constructor with the skip and the view
+    /// mode of the current one, the constructor adds its bind groups to the
+    /// given ones.
+    fn build_pipeline(
+        &self,
+        i: usize,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> Result<(Box<dyn PipelineState>, Vec<std::path::PathBuf>), String> {
+        let source = &self.pipeline_sources[i];
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let (mut pipeline, record) = record_shaders(|| {
+            (source.constructor)(&self.device, &self.config, &self.projection, bind_groups)
+        });
+        let scope_error = pollster::block_on(self.device.pop_error_scope());
+        // the errors of the preprocessor point at the file and line that was written
+        let error = record
+            .errors
+            .into_iter()
+            .next()
+            .or(scope_error.map(|err| err.to_string()));
+        if let Some(err) = error {
+            return Err(err);
+        }
+        pipeline.set_skip(self.pipelines[i].get_skip());
+        if let Some(view_mode) = self.pipelines[i].get_view_mode() {
+            pipeline.set_view_mode(view_mode);
+        }
+        Ok((pipeline, record.files))
+    }
+
+    /// Builds a pipeline again and keeps the old one when that fails, the
+    /// error is kept in its source. The constructor works on a copy of the
+    /// bind groups, kept only once it succeeded.
+    fn rebuild_pipeline(&mut self, i: usize) {
+        let mut bind_groups = self.bind_groups.clone();
+        match self.build_pipeline(i, &mut bind_groups) {
+            Ok((pipeline, files)) => {
+                info!("rebuilt {}", pipeline.get_name());
+                self.pipelines[i] = pipeline;
+                self.bind_groups = bind_groups;
+                self.pipeline_sources[i].shaders = files;
+                self.pipeline_sources[i].error = None;
+            }
+            Err(err) => {
+                error!("failed to rebuild {}: {err}", self.pipelines[i].get_name());
+                self.pipeline_sources[i].error = Some(err);
+            }
+        }
+    }
+
+    /// Switches the depth convention, every pipeline is built again with its
+    /// depth test. They switch together or not at all, a pipeline left with
+    /// the other depth test would fail against the shared depth buffer.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        if self.projection.reverse_z == reverse_z {
+            return;
+        }
+        self.projection.reverse_z = reverse_z;
+        let mut bind_groups = self.bind_groups.clone();
+        let built: Result<Vec<_>, _> = (0..self.pipelines.len())
+            .map(|i| {
+                self.build_pipeline(i, &mut bind_groups)
+                    .map_err(|err| (i, err))
+            })
+            .collect();
+        match built {
+            Ok(built) => {
+                for (i, (pipeline, files)) in built.into_iter().enumerate() {
+                    self.pipelines[i] = pipeline;
+                    self.pipeline_sources[i].shaders = files;
+                    self.pipeline_sources[i].error = None;
+                }
+                self.bind_groups = bind_groups;
+                info!("rebuilt the pipelines with reverse z {reverse_z}");
+            }
+            Err((i, err)) => {
+                self.projection.reverse_z = !reverse_z;
+                error!(
+                    "failed to rebuild {} with reverse z {reverse_z}, keeping the depth convention: {err}",
+                    self.pipelines[i].get_name()
+                );
+                self.pipeline_sources[i].error = Some(err);
+            }
+        }
+        self.show_pipeline_errors();
+    }
+
+    fn show_pipeline_errors(&mut self) {
+        let error = self
+            .pipeline_sources
+            .iter()
+            .find_map(|source| source.error.as_ref());
+
+        // the error goes in the title as well, the debug ui draws a red frame
+        // around the window and the first line of the error
+        if let Some(window) = self.window {
+            match &error {
+                Some(err) => {
+                    let first_line = err.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+                    window.set_title(&format!(
+                        "{} - shader error: {first_line}",
+                        self.window_title
+                    ));
+                }
+                None => window.set_title(&self.window_title),
+            }
         }
+        self.uniform_ui.shader_error = error.is_some() as u32;
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        self.attachments
-            .insert("color".into(), Attachment::Color(ColorAttachment { view }));
+        self.reload_shaders();
+        self.captures.poll(&self.device);
+
+        // headless the "surface" attachment is a texture that stays around
+        let output = match &self.surface {
+            Some(surface) => Some(surface.get_current_texture()?),
+            None => None,
+        };
+        if let Some(output) = &output {
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            self.attachments.insert(
+                "surface".into(),
+                Attachment::Color(ColorAttachment {
+                    view,
+                    texture: None,
+                }),
+            );
+        }
 
         let mut encoder = self
             .device
@@ -487,26 +1179,134 @@ impl<'a> RenderState<'a> {
                 label: Some("Render Encoder"),
             });
 
+        // the offscreen color persists between frames, start from black
+        if let Some(Attachment::Color(color_attachment)) = self.attachments.get("color") {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clear Color Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &color_attachment.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        }
+
+        self.profiler.begin_frame(&self.device);
         let mut clear_depth = true;
         for pipeline in self.pipelines.iter() {
             if !pipeline.get_skip() {
-                pipeline.render(
+                pipeline.render_profiled(
                     &mut encoder,
                     &self.bind_groups,
                     &self.attachments,
                     clear_depth,
+                    &mut self.profiler,
                 );
                 clear_depth = false;
             }
         }
+        self.present.render_profiled(
+            &mut encoder,
+            &self.bind_groups,
+            &self.attachments,
+            false,
+            &mut self.profiler,
+        );
+        self.profiler.resolve(&mut encoder);
+
+        if self.captures.is_requested() {
+            let surface_texture = match &output {
+                Some(output) => Some(&output.texture),
+                None => match self.attachments.get("surface") {
+                    Some(Attachment::Color(surface)) => surface.texture.as_ref(),
+                    _ => None,
+                },
+            }
+            .filter(|t| t.usage().contains(wgpu::TextureUsages::COPY_SRC));
+            let color_texture = match self.attachments.get("color") {
+                Some(Attachment::Color(color)) => color.texture.as_ref(),
+                _ => None,
+            };
+            // the surface can't always be copied, the scene color is the closest
+            // frame but not the one on screen
+            match (surface_texture, color_texture) {
+                (Some(texture), _) => self.captures.encode(&self.device, &mut encoder, texture),
+                (None, Some(texture)) => {
+                    warn!("the surface can't be copied, capturing the scene color instead");
+                    self.captures.use_scene_color();
+                    self.captures.encode(&self.device, &mut encoder, texture);
+                }
+                (None, None) => {
+                    error!("no texture to capture the frame from");
+                    self.captures.cancel();
+                }
+            }
+        }
 
         self.queue.submit(Some(encoder.finish()));
-        output.present();
+        self.captures.submitted();
+        self.profiler.submitted();
+        if let Some(output) = output {
+            output.present();
+        }
+
+        // this frame's accumulated gi is the history of the next one
+        swap_attachments(
+            &mut self.attachments,
+            &mut self.bind_groups,
+            "gi_history",
+            "gi_history_prev",
+        );
+        swap_attachments(
+            &mut self.attachments,
+            &mut self.bind_groups,
+            "taa_history",
+            "taa_history_prev",
+        );
 
         Ok(())
     }
 }
 
+async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: adapter.features() & PROFILER_FEATURES,
+                required_limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                },
+            },
+            None,
+        )
+        .await
+        .unwrap()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Radical inverse of index in the given base, a low discrepancy sequence in 0..1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
 #[derive(Clone, Debug, Default)]
 struct KeyState {
     just_pressed: bool,
@@ -517,8 +1317,11 @@ struct KeyState {
 #[derive(Clone, Debug, Default)]
 struct InputState {
     map: HashMap<KeyCode, KeyState>,
+    mouse_buttons: HashMap<MouseButton, KeyState>,
     mouse_pos: Vec2,
     mouse_moved: Vec2,
+    // lines of the mouse wheel since the last update, positive away from the user
+    scroll: f32,
 }
 
 #[allow(dead_code)]
@@ -526,8 +1329,10 @@ impl InputState {
     fn new() -> Self {
         Self {
             map: HashMap::new(),
+            mouse_buttons: HashMap::new(),
             mouse_pos: Vec2::ZERO,
             mouse_moved: Vec2::ZERO,
+            scroll: 0.0,
         }
     }
 
@@ -560,15 +1365,28 @@ impl InputState {
                 keystate.just_released = state == &ElementState::Released;
                 keystate.pressed = keystate.just_pressed;
             }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let keystate = self.mouse_buttons.entry(*button).or_default();
+                keystate.just_pressed = state == &ElementState::Pressed;
+                keystate.just_released = state == &ElementState::Released;
+                keystate.pressed = keystate.just_pressed;
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 self.mouse_pos = Vec2::new(position.x as f32, position.y as f32)
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly the pixels of a line
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+            }
             _ => {}
         }
     }
 
     fn update(&mut self) {
-        for (_, state) in self.map.iter_mut() {
+        for state in self.map.values_mut().chain(self.mouse_buttons.values_mut()) {
             if state.pressed && state.just_released {
                 state.pressed = false;
             }
@@ -576,6 +1394,7 @@ impl InputState {
             state.just_released = false;
         }
         self.mouse_moved = Vec2::ZERO;
+        self.scroll = 0.0;
     }
 
     fn is_pressed(&self, keycode: &KeyCode) -> bool {
@@ -587,13 +1406,78 @@ impl InputState {
     fn is_just_released(&self, keycode: &KeyCode) -> bool {
         self.map.get(keycode).is_some_and(|s| s.just_released)
     }
+    fn is_mouse_pressed(&self, button: &MouseButton) -> bool {
+        self.mouse_buttons.get(button).is_some_and(|s| s.pressed)
+    }
+    fn is_mouse_just_pressed(&self, button: &MouseButton) -> bool {
+        self.mouse_buttons
+            .get(button)
+            .is_some_and(|s| s.just_pressed)
+    }
+}
+
+/// The time of the simulated world, it moves the sun and the moon.
+#[derive(Clone, Debug)]
+pub struct WorldClock {
+    // fraction of the day, 0 is midnight and 0.5 is noon
+    pub time_of_day: f32,
+    // seconds of simulation in a day at speed 1
+    pub day_length: f32,
+    pub speed: f32,
+    pub paused: bool,
+    // seconds of simulation since the start, regardless of speed and pause
+    pub elapsed: f32,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self {
+            time_of_day: 0.35,
+            day_length: 240.0,
+            speed: 1.0,
+            paused: false,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl WorldClock {
+    fn advance(&mut self, dt: f32) {
+        self.elapsed += dt;
+        if !self.paused {
+            self.time_of_day =
+                (self.time_of_day + dt * self.speed / self.day_length).rem_euclid(1.0);
+        }
+    }
+
+    /// Points towards the sun, it rises at 6 towards +x and sets at 18 towards -x.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.time_of_day - 0.25) * 2.0 * PI;
+        // tilted so that the sun is never straight above the scene
+        Quat::from_rotation_x(0.5) * Vec3::new(angle.cos(), angle.sin(), 0.0)
+    }
+
+    /// Points towards the moon, always full and opposite to the sun.
+    pub fn moon_direction(&self) -> Vec3 {
+        -self.sun_direction()
+    }
+
+    pub fn hours(&self) -> f32 {
+        self.time_of_day * 24.0
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct SimulationState {
     pub camera_position: Vec3,
     pub camera_rotation: Quat,
+    pub camera_mode: CameraMode,
+    // the height of the view in world units of the orthographic modes
+    pub camera_zoom: f32,
     pub universe: Universe,
+    pub clock: WorldClock,
+    pub show_chunk_bounds: bool,
+    pub camera_path: Option<CameraPathState>,
 }
 
 impl SimulationState {
@@ -601,23 +1485,151 @@ impl SimulationState {
         Self {
             camera_position: Vec3::ZERO,
             camera_rotation: Quat::from_rotation_z(PI * 0.5) * Quat::from_rotation_x(PI),
+            camera_mode: CameraMode::Perspective,
+            camera_zoom: 32.0,
             universe: simple_universe(),
+            clock: WorldClock::default(),
+            show_chunk_bounds: false,
+            camera_path: None,
+        }
+    }
+
+    /// The isometric mode turns the camera to the isometric angle, leaving it
+    /// turns it back to where it was pointing.
+    pub fn set_camera_mode(&mut self, camera_mode: CameraMode) {
+        if camera_mode == CameraMode::Isometric {
+            self.camera_rotation = isometric_rotation();
+        }
+        self.camera_mode = camera_mode;
+    }
+
+    pub fn is_playing_camera_path(&self) -> bool {
+        matches!(self.camera_path, Some(CameraPathState::Playing { .. }))
+    }
+
+    /// Starts recording the camera path, or saves the one being recorded.
+    fn toggle_camera_recording(&mut self) {
+        match self.camera_path.take() {
+            Some(CameraPathState::Recording { path, .. }) => match path.save() {
+                Ok(file) => info!(
+                    "wrote {} camera keyframes to {file:?}",
+                    path.keyframes.len()
+                ),
+                Err(err) => error!("can't write the camera path: {err}"),
+            },
+            _ => {
+                info!("recording the camera path");
+                self.camera_path = Some(CameraPathState::Recording {
+                    path: CameraPath::default(),
+                    time: 0.0,
+                });
+            }
+        }
+    }
+
+    /// Plays the latest camera path, or stops the one playing.
+    fn toggle_camera_playback(&mut self) {
+        if let Some(CameraPathState::Playing { .. }) = self.camera_path.take() {
+            info!("stopped the camera path");
+            return;
+        }
+        let Some(file) = CameraPath::playback_file() else {
+            warn!("no camera path in {CAMERA_PATH_DIRECTORY:?}");
+            return;
+        };
+        match CameraPath::load(&file) {
+            Ok(path) => {
+                info!("playing {file:?} for {:.2}s", path.duration());
+                self.camera_path = Some(CameraPathState::Playing { path, time: 0.0 });
+            }
+            Err(err) => error!("can't read {file:?}: {err}"),
+        }
+    }
+
+    /// The debug lines of the simulation, drawn again every frame.
+    fn draw_debug(&self, lines: &mut DebugLines) {
+        if self.show_chunk_bounds {
+            let chunk_size = Vec3::splat(CHUNK_SIDE as f32);
+            for chunk_pos in self.universe.chunks.keys() {
+                let min = chunk_pos.as_vec3();
+                lines.aabb(min, min + chunk_size, Vec4::new(1.0, 0.8, 0.2, 1.0));
+            }
         }
     }
 
     fn update(&mut self, time_delta: Duration, input_state: &mut InputState) {
         let dt = time_delta.as_secs_f32();
 
+        self.clock.advance(dt);
+        if input_state.is_just_pressed(&KeyCode::KeyP) {
+            self.clock.paused = !self.clock.paused;
+            info!("clock paused: {}", self.clock.paused);
+        }
+        if input_state.is_just_pressed(&KeyCode::BracketRight) {
+            self.clock.speed *= 2.0;
+            info!("clock speed: {}", self.clock.speed);
+        }
+        if input_state.is_just_pressed(&KeyCode::BracketLeft) {
+            self.clock.speed *= 0.5;
+            info!("clock speed: {}", self.clock.speed);
+        }
+        // step the time of day by one hour
+        if input_state.is_just_pressed(&KeyCode::Period) {
+            self.clock.time_of_day = (self.clock.time_of_day + 1.0 / 24.0).rem_euclid(1.0);
+            info!("time of day: {:.1}h", self.clock.hours());
+        }
+        if input_state.is_just_pressed(&KeyCode::Comma) {
+            self.clock.time_of_day = (self.clock.time_of_day - 1.0 / 24.0).rem_euclid(1.0);
+            info!("time of day: {:.1}h", self.clock.hours());
+        }
+
+        if input_state.is_just_pressed(&KeyCode::KeyC) {
+            self.show_chunk_bounds = !self.show_chunk_bounds;
+        }
+
+        if input_state.is_just_pressed(&KeyCode::KeyR) {
+            self.toggle_camera_recording();
+        }
+        if input_state.is_just_pressed(&KeyCode::KeyN) {
+            self.toggle_camera_playback();
+        }
+
+        // a camera path being played moves the camera instead of the input
+        if let Some(CameraPathState::Playing { path, time }) = &mut self.camera_path {
+            let sample = path.sample(*time);
+            *time += dt;
+            if let Some((position, rotation)) = sample {
+                self.camera_position = position;
+                self.camera_rotation = rotation;
+                return;
+            }
+            info!("the camera path ended");
+            self.camera_path = None;
+        }
+
+        if input_state.is_just_pressed(&KeyCode::KeyO) {
+            self.set_camera_mode(self.camera_mode.next());
+            info!("camera mode: {}", self.camera_mode.label());
+        }
+        // the wheel zooms the parallel views in and out
+        if self.camera_mode.is_orthographic() && input_state.scroll != 0.0 {
+            self.camera_zoom =
+                (self.camera_zoom * 0.9f32.powf(input_state.scroll)).clamp(1.0, 1024.0);
+        }
+
         let speed = 3.0;
         let mouse_sensitivity = Vec2::new(1.0, 1.0) * 0.1;
 
-        let (mut yaw, mut pitch, _) = self.camera_rotation.to_euler(EulerRot::YXZ);
-        pitch -= (mouse_sensitivity.y * input_state.mouse_moved.y).to_radians();
-        yaw -= (mouse_sensitivity.x * input_state.mouse_moved.x).to_radians();
-        pitch = pitch.clamp(-1.54, 1.54);
-        let yaw_rot = Quat::from_axis_angle(Vec3::Y, yaw);
-        let pitch_rot = Quat::from_axis_angle(Vec3::X, pitch);
-        self.camera_rotation = yaw_rot * pitch_rot;
+        // the isometric angle is fixed, only the position moves
+        if self.camera_mode != CameraMode::Isometric {
+            let (mut yaw, mut pitch, _) = self.camera_rotation.to_euler(EulerRot::YXZ);
+            pitch -= (mouse_sensitivity.y * input_state.mouse_moved.y).to_radians();
+            yaw -= (mouse_sensitivity.x * input_state.mouse_moved.x).to_radians();
+            pitch = pitch.clamp(-1.54, 1.54);
+            let yaw_rot = Quat::from_axis_angle(Vec3::Y, yaw);
+            let pitch_rot = Quat::from_axis_angle(Vec3::X, pitch);
+            self.camera_rotation = yaw_rot * pitch_rot;
+        }
 
         let mut acceleration = Vec3::ZERO;
         if input_state.is_pressed(&KeyCode::KeyW) {
@@ -644,6 +1656,12 @@ impl SimulationState {
             1.0
         };
         self.camera_position += self.camera_rotation * acceleration * speed * boost * dt;
+
+        let pose = CameraPose::from_sim_state(self);
+        if let Some(CameraPathState::Recording { path, time }) = &mut self.camera_path {
+            path.record(*time, pose);
+            *time += dt;
+        }
     }
 }
 
@@ -662,62 +1680,178 @@ pub async fn run() {
     let mut time_simulation = Duration::ZERO;
     let mut time_frame = Instant::now();
     let mut time_accumulator = Duration::ZERO;
-    let time_delta = Duration::from_millis(20);
+    let time_delta = SIMULATION_TIMESTEP;
 
     let mut sim_state = SimulationState::new();
     let mut input_state = InputState::new();
     let mut rendered = false;
+    let mut recording: Option<Recording> = None;
+    let mut frame_stats = FrameStats::with_csv(FrameStats::csv_path()).unwrap_or_else(|err| {
+        error!("can't write the frame stats: {err}");
+        FrameStats::default()
+    });
+    let mut time_summary = Instant::now();
+    let mut time_last_rendered = Instant::now();
 
     event_loop
         .run(move |event, control_flow| {
             // update the simulation
             let duration_frame = Instant::now() - time_frame;
             time_frame = Instant::now();
-            time_accumulator += duration_frame;
+            let fixed_timestep = match &recording {
+                Some(recording) => Some(recording.timestep),
+                // the same frames of a camera path however fast they render
+                None => sim_state.is_playing_camera_path().then_some(time_delta),
+            };
+            match fixed_timestep {
+                // one fixed step per rendered frame, for a smooth sequence
+                Some(timestep) if rendered => time_accumulator += timestep,
+                Some(_) => {}
+                None => time_accumulator += duration_frame,
+            }
             while time_accumulator >= time_delta {
+                // the cursor moves over the panel instead of the camera
+                if input_state.is_just_pressed(&debug_ui::PANEL_KEY) {
+                    render_state.toggle_debug_panel();
+                }
+                if render_state.debug_panel.open {
+                    input_state.mouse_moved = Vec2::ZERO;
+                }
+                render_state.debug_panel_input(&mut sim_state, &input_state);
+
                 sim_state.update(time_delta, &mut input_state);
 
                 // debug change render pass
-                let mut indices = vec![];
-                if input_state.is_just_pressed(&KeyCode::Digit1) {
-                    indices.push(0);
+                let indices: Vec<usize> = PIPELINE_KEYS
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (key, _))| input_state.is_just_pressed(key))
+                    .map(|(i, _)| i)
+                    .collect();
+                let skips: Vec<bool> = render_state
+                    .pipelines
+                    .iter()
+                    .map(|p| p.get_skip())
+                    .collect();
+                for i in indices {
+                    if i < render_state.pipelines.len() {
+                        render_state.pipelines[i].set_skip(!skips[i]);
+                    }
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit2) {
-                    indices.push(1);
+
+                // debug freeze the frustum where the camera is
+                if input_state.is_just_pressed(&KeyCode::KeyF) {
+                    render_state.frozen_world_from_clip = match render_state.frozen_world_from_clip
+                    {
+                        Some(_) => None,
+                        None => {
+                            // drawn up to the finite far plane, reverse z puts it at infinity
+                            let projection = Projection {
+                                reverse_z: false,
+                                ..render_state.projection
+                            };
+                            let viewport_size = render_state.uniform_global.viewport_size;
+                            let clip_from_view =
+                                projection.clip_from_view(viewport_size.x / viewport_size.y);
+                            let view_from_world = render_state.uniform_global.view_from_world;
+                            Some((clip_from_view * view_from_world).inverse())
+                        }
+                    };
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit3) {
-                    indices.push(2);
+
+                // debug switch between reverse z and the standard depth range
+                if input_state.is_just_pressed(&KeyCode::KeyZ) {
+                    render_state.set_reverse_z(!render_state.projection.reverse_z);
+                    info!("reverse z: {}", render_state.projection.reverse_z);
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit4) {
-                    indices.push(3);
+
+                // debug switch between forward and deferred shading
+                if input_state.is_just_pressed(&KeyCode::KeyG) {
+                    render_state.deferred = !render_state.deferred;
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit5) {
-                    indices.push(4);
+
+                // debug cycle the number of gi rays per pixel
+                if input_state.is_just_pressed(&KeyCode::KeyI) {
+                    render_state.gi_ray_count = match render_state.gi_ray_count {
+                        0 => 1,
+                        1 => 2,
+                        2 => 4,
+                        4 => 8,
+                        _ => 0,
+                    };
+                    info!("gi rays per pixel: {}", render_state.gi_ray_count);
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit6) {
-                    indices.push(5);
+
+                // debug cycle the view mode of every voxel raycaster together
+                if input_state.is_just_pressed(&KeyCode::KeyM) {
+                    let view_mode = render_state
+                        .pipelines
+                        .iter()
+                        .find_map(|p| p.get_view_mode())
+                        .unwrap_or_default()
+                        .next();
+                    for pipeline in render_state.pipelines.iter_mut() {
+                        pipeline.set_view_mode(view_mode);
+                    }
+                    info!("view mode: {}", view_mode.label());
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit7) {
-                    indices.push(6);
+
+                // debug post processing settings
+                let post = &mut render_state.uniform_post;
+                if input_state.is_just_pressed(&KeyCode::Minus) {
+                    post.exposure *= 0.8;
+                    info!("exposure: {}", post.exposure);
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit8) {
-                    indices.push(7);
+                if input_state.is_just_pressed(&KeyCode::Equal) {
+                    post.exposure *= 1.25;
+                    info!("exposure: {}", post.exposure);
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit9) {
-                    indices.push(8);
+                if input_state.is_just_pressed(&KeyCode::KeyT) {
+                    post.tonemapper = (post.tonemapper + 1) % 3;
+                    info!(
+                        "tonemapper: {}",
+                        ["clamp", "aces", "agx"][post.tonemapper as usize]
+                    );
                 }
-                if input_state.is_just_pressed(&KeyCode::Digit0) {
-                    indices.push(9);
+                if input_state.is_just_pressed(&KeyCode::KeyB) {
+                    post.bloom_intensity = if post.bloom_intensity > 0.0 {
+                        0.0
+                    } else {
+                        PostUniform::default().bloom_intensity
+                    };
                 }
-                let skips: Vec<bool> = render_state
-                    .pipelines
-                    .iter()
-                    .map(|p| p.get_skip())
-                    .collect();
-                for i in indices {
-                    if i < render_state.pipelines.len() {
-                        render_state.pipelines[i].set_skip(!skips[i]);
-                    }
+                if input_state.is_just_pressed(&KeyCode::KeyL) {
+                    post.lut_strength = 1.0 - post.lut_strength;
+                }
+                if input_state.is_just_pressed(&KeyCode::KeyV) {
+                    post.vignette_strength = if post.vignette_strength > 0.0 {
+                        0.0
+                    } else {
+                        PostUniform::default().vignette_strength
+                    };
+                }
+                if input_state.is_just_pressed(&KeyCode::KeyX) {
+                    post.fxaa = 1 - post.fxaa;
+                }
+
+                if input_state.is_just_pressed(&KeyCode::F12) {
+                    render_state.screenshot(&sim_state);
+                }
+                if input_state.is_just_pressed(&KeyCode::F10) {
+                    recording = match recording.take() {
+                        Some(recording) => {
+                            info!("stopped recording to {:?}", recording.directory);
+                            None
+                        }
+                        None => {
+                            let recording = Recording::new(time_delta);
+                            info!(
+                                "recording every {} frames to {:?}",
+                                recording.every, recording.directory
+                            );
+                            Some(recording)
+                        }
+                    };
                 }
 
                 input_state.update();
@@ -725,17 +1859,16 @@ pub async fn run() {
                 time_simulation += time_delta;
             }
 
+            // the frame time is between the ends of two rendered frames
             if rendered {
                 rendered = false;
-                if duration_frame < Duration::from_millis(10) {
-                    info!(target: "timing", "rendered in {}ms", duration_frame.as_nanos() as f64 / 1000000.0);
-                }
-                else if duration_frame < Duration::from_millis(100) {
-                    warn!(target: "timing", "rendered in {}ms", duration_frame.as_nanos() as f64 / 1000000.0);
-                }
-                else {
-                    error!(target: "timing", "rendered in {}ms", duration_frame.as_nanos() as f64 / 1000000.0);
-                }
+                frame_stats.add(time_last_rendered.elapsed(), time_simulation);
+                time_last_rendered = Instant::now();
+            }
+            if time_summary.elapsed() >= FRAME_STATS_INTERVAL {
+                time_summary = Instant::now();
+                frame_stats.log();
+                render_state.profiler.log();
             }
 
             // render
@@ -759,16 +1892,21 @@ pub async fn run() {
                                     ..
                                 },
                             ..
-                        } => control_flow.exit(),
+                        } => {
+                            render_state.captures.flush(&render_state.device);
+                            frame_stats.log();
+                            match frame_stats.finish_csv() {
+                                Some(Ok(path)) => info!("wrote {path:?}"),
+                                Some(Err(err)) => error!("can't write the frame stats: {err}"),
+                                None => {}
+                            }
+                            control_flow.exit();
+                        }
                         WindowEvent::Resized(physical_size) => {
                             log::info!("physical_size: {physical_size:?}");
                             surface_configured = true;
                             render_state.resize(*physical_size);
-
-                            render_state
-                                .window()
-                                .set_cursor_grab(winit::window::CursorGrabMode::Confined)
-                                .unwrap();
+                            render_state.grab_cursor();
                         }
                         WindowEvent::RedrawRequested => {
                             render_state.window().request_redraw();
@@ -778,6 +1916,13 @@ pub async fn run() {
                             }
 
                             render_state.extract(&mut sim_state);
+                            if let Some(recording) = &mut recording {
+                                let text = render_state.capture_text(&sim_state);
+                                if let Some(capture) = recording.next_frame(text) {
+                                    render_state.captures.wait_for_room(&render_state.device);
+                                    render_state.captures.request(capture);
+                                }
+                            }
                             match render_state.render() {
                                 Ok(_) => {
                                     rendered = true;
@@ -807,5 +1952,12 @@ pub async fn run() {
 }
 
 fn main() {
-    pollster::block_on(run());
+    match HeadlessOptions::from_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => pollster::block_on(run_headless(options)),
+        Ok(None) => pollster::block_on(run()),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    }
 }
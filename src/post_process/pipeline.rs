@@ -0,0 +1,355 @@
+use crate::*;
+
+// the chain of passes between the hdr scene color and the surface:
+// bloom, then exposure, tonemapping, color grading and vignette, then fxaa.
+// the result is written back to "color" so the debug pipelines draw on top of it
+pub struct Pipeline {
+    pipeline_bloom_prefilter: wgpu::RenderPipeline,
+    pipeline_bloom_downsample: wgpu::RenderPipeline,
+    pipeline_bloom_upsample: wgpu::RenderPipeline,
+    pipeline_composite: wgpu::RenderPipeline,
+    pipeline_fxaa: wgpu::RenderPipeline,
+    skip: bool,
+    //
+    resources_bind_group: BindGroupState,
+    lut_texture: wgpu::Texture,
+    lut_uploaded: bool,
+}
+
+pub const PIPELINE_NAME: &str = "Post Process";
+
+const LUT_SIZE: u32 = 32;
+
+/// A mild grade applied in sRGB space: a soft s-curve with warmer highlights.
+fn grading_lut() -> Vec<u8> {
+    let mut data = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+    for b in 0..LUT_SIZE {
+        for g in 0..LUT_SIZE {
+            for r in 0..LUT_SIZE {
+                let color = Vec3::new(r as f32, g as f32, b as f32) / (LUT_SIZE - 1) as f32;
+                let curve = color * color * (Vec3::splat(3.0) - 2.0 * color);
+                let graded = color.lerp(curve, 0.3) * Vec3::new(1.03, 1.0, 0.96);
+                let graded = (graded.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+                data.extend_from_slice(&[graded.x as u8, graded.y as u8, graded.z as u8, 255]);
+            }
+        }
+    }
+    data
+}
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(&(PIPELINE_NAME.to_string() + " " + label + " Render Pass")),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+    render_pass.set_pipeline(pipeline);
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(i as u32, bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+impl PipelineState for Pipeline {
+    fn get_name(&self) -> String {
+        PIPELINE_NAME.to_string()
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> Self {
+        let Some(post_bind_group) = bind_groups.get("post_settings") else {
+            panic!("post_settings bind group missing");
+        };
+        // every screen texture shares the same layout, any of them works
+        let Some(input_bind_group) = bind_groups.get("color") else {
+            panic!("color bind group missing");
+        };
+
+        let lut_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Grading Lut Texture"),
+            size: wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: LUT_SIZE,
+                depth_or_array_layers: LUT_SIZE,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let lut_view = lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let resources_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("post_resources_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let resources_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &resources_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&lut_view),
+                },
+            ],
+            label: Some("post_resources_bind_group"),
+        });
+        let resources_bind_group = BindGroupState {
+            buffer: vec![],
+            bind_group: resources_bind_group,
+            bind_group_layout: resources_bind_group_layout,
+        };
+
+        let shader = include_shader!(device, "post_process.wgsl");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
+            bind_group_layouts: &[
+                &post_bind_group.bind_group_layout,
+                &input_bind_group.bind_group_layout,
+                &resources_bind_group.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        // the composite also reads the bloom chain
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Composite Render Pipeline Layout")),
+                bind_group_layouts: &[
+                    &post_bind_group.bind_group_layout,
+                    &input_bind_group.bind_group_layout,
+                    &resources_bind_group.bind_group_layout,
+                    &input_bind_group.bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |layout, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: SCENE_COLOR_FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let replace = wgpu::BlendState::REPLACE;
+
+        Self {
+            pipeline_bloom_prefilter: create_pipeline(
+                &pipeline_layout,
+                "fs_bloom_prefilter",
+                replace,
+            ),
+            pipeline_bloom_downsample: create_pipeline(
+                &pipeline_layout,
+                "fs_bloom_downsample",
+                replace,
+            ),
+            pipeline_bloom_upsample: create_pipeline(
+                &pipeline_layout,
+                "fs_bloom_upsample",
+                additive,
+            ),
+            pipeline_composite: create_pipeline(
+                &composite_pipeline_layout,
+                "fs_composite",
+                replace,
+            ),
+            pipeline_fxaa: create_pipeline(&pipeline_layout, "fs_fxaa", replace),
+            skip: false,
+            resources_bind_group,
+            lut_texture,
+            lut_uploaded: false,
+        }
+    }
+
+    fn extract(
+        &mut self,
+        _sim_state: &mut SimulationState,
+        _frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
+        if self.lut_uploaded {
+            return;
+        }
+        queue.write_texture(
+            self.lut_texture.as_image_copy(),
+            &grading_lut(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * LUT_SIZE),
+                rows_per_image: Some(LUT_SIZE),
+            },
+            self.lut_texture.size(),
+        );
+        self.lut_uploaded = true;
+    }
+
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        _clear_depth: bool,
+    ) {
+        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+            return;
+        };
+        let Some(Attachment::Color(post_attachment)) = attachments.get("post") else {
+            return;
+        };
+        let Some(post_settings_bind_group) = bind_groups.get("post_settings") else {
+            return;
+        };
+        let Some(color_bind_group) = bind_groups.get("color") else {
+            return;
+        };
+        let Some(post_bind_group) = bind_groups.get("post") else {
+            return;
+        };
+        let mut bloom = vec![];
+        for i in 0..BLOOM_MIP_COUNT {
+            let name = format!("bloom_{i}");
+            let (Some(Attachment::Color(attachment)), Some(bind_group)) =
+                (attachments.get(&name), bind_groups.get(&name))
+            else {
+                return;
+            };
+            bloom.push((attachment, bind_group));
+        }
+        let settings = &post_settings_bind_group.bind_group;
+        let resources = &self.resources_bind_group.bind_group;
+
+        fullscreen_pass(
+            encoder,
+            "Bloom Prefilter",
+            &self.pipeline_bloom_prefilter,
+            &bloom[0].0.view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &[settings, &color_bind_group.bind_group, resources],
+        );
+        for i in 1..BLOOM_MIP_COUNT {
+            fullscreen_pass(
+                encoder,
+                "Bloom Downsample",
+                &self.pipeline_bloom_downsample,
+                &bloom[i].0.view,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                &[settings, &bloom[i - 1].1.bind_group, resources],
+            );
+        }
+        // each level accumulates the blurred levels below it
+        for i in (1..BLOOM_MIP_COUNT).rev() {
+            fullscreen_pass(
+                encoder,
+                "Bloom Upsample",
+                &self.pipeline_bloom_upsample,
+                &bloom[i - 1].0.view,
+                wgpu::LoadOp::Load,
+                &[settings, &bloom[i].1.bind_group, resources],
+            );
+        }
+        fullscreen_pass(
+            encoder,
+            "Composite",
+            &self.pipeline_composite,
+            &post_attachment.view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &[
+                settings,
+                &color_bind_group.bind_group,
+                resources,
+                &bloom[0].1.bind_group,
+            ],
+        );
+        fullscreen_pass(
+            encoder,
+            "FXAA",
+            &self.pipeline_fxaa,
+            &color_attachment.view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &[settings, &post_bind_group.bind_group, resources],
+        );
+    }
+
+    fn get_skip(&self) -> bool {
+        self.skip
+    }
+
+    fn set_skip(&mut self, skip: bool) {
+        self.skip = skip
+    }
+}
@@ -0,0 +1,207 @@
+#import fullscreen
+
+struct PostUniform {
+    exposure: f32,
+    // 0: clamp, 1: aces, 2: agx
+    tonemapper: u32,
+    bloom_intensity: f32,
+    bloom_threshold: f32,
+    lut_strength: f32,
+    vignette_strength: f32,
+    fxaa: u32,
+    _padding: u32,
+};
+@group(0) @binding(0)
+var<uniform> post: PostUniform;
+
+@group(1) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(2) @binding(0)
+var linear_sampler: sampler;
+@group(2) @binding(1)
+var lut_texture: texture_3d<f32>;
+
+@group(3) @binding(0)
+var bloom_texture: texture_2d<f32>;
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_texture, linear_sampler, uv, 0.0);
+}
+
+fn input_texel_size() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(input_texture));
+}
+
+// four bilinear taps cover a 4x4 block of the larger input
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = input_texel_size();
+    let color = sample_input(uv + texel * vec2<f32>(-1.0, -1.0))
+        + sample_input(uv + texel * vec2<f32>(1.0, -1.0))
+        + sample_input(uv + texel * vec2<f32>(-1.0, 1.0))
+        + sample_input(uv + texel * vec2<f32>(1.0, 1.0));
+    return color.rgb * 0.25;
+}
+
+@fragment
+fn fs_bloom_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.uv) * post.exposure;
+
+    // soft knee around the threshold to avoid hard edges in the bloom
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = post.bloom_threshold * 0.5;
+    var soft = clamp(brightness - post.bloom_threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+    let contribution = max(soft, brightness - post.bloom_threshold) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_bloom_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter of the smaller input, added on top of the larger level
+@fragment
+fn fs_bloom_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = input_texel_size();
+    var color = sample_input(in.uv) * 4.0;
+    color += (sample_input(in.uv + texel * vec2<f32>(-1.0, 0.0))
+        + sample_input(in.uv + texel * vec2<f32>(1.0, 0.0))
+        + sample_input(in.uv + texel * vec2<f32>(0.0, -1.0))
+        + sample_input(in.uv + texel * vec2<f32>(0.0, 1.0))) * 2.0;
+    color += sample_input(in.uv + texel * vec2<f32>(-1.0, -1.0))
+        + sample_input(in.uv + texel * vec2<f32>(1.0, -1.0))
+        + sample_input(in.uv + texel * vec2<f32>(-1.0, 1.0))
+        + sample_input(in.uv + texel * vec2<f32>(1.0, 1.0));
+    return vec4<f32>(color.rgb / 16.0, 1.0);
+}
+
+// Narkowicz fit of the aces filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+// minimal agx with the default look, from linear rec709 to linear display
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let agx_mat = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let agx_mat_inv = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    var c = agx_mat * color;
+    c = clamp(log2(max(c, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    c = (c - min_ev) / (max_ev - min_ev);
+    c = agx_mat_inv * agx_contrast(c);
+    return pow(clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// the lut is authored on srgb values, sample at texel centers
+fn grade(color: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(lut_texture).x);
+    let coord = linear_to_srgb(color) * (size - 1.0) / size + 0.5 / size;
+    let graded = textureSampleLevel(lut_texture, linear_sampler, coord, 0.0).rgb;
+    return srgb_to_linear(graded);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureLoad(input_texture, vec2<i32>(in.position.xy), 0).rgb * post.exposure;
+    color += textureSampleLevel(bloom_texture, linear_sampler, in.uv, 0.0).rgb * post.bloom_intensity;
+
+    switch post.tonemapper {
+        case 1u: {
+            color = aces(color);
+        }
+        case 2u: {
+            color = agx(color);
+        }
+        default: {
+            color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+    }
+
+    color = mix(color, grade(color), post.lut_strength);
+
+    let distance_from_center = distance(in.uv, vec2<f32>(0.5));
+    color *= 1.0 - post.vignette_strength * smoothstep(0.2, 0.8, distance_from_center);
+
+    // fxaa finds edges on the perceptual luma kept in alpha
+    return vec4<f32>(color, sqrt(luma(color)));
+}
+
+const FXAA_REDUCE_MIN = 1.0 / 128.0;
+const FXAA_REDUCE_MUL = 1.0 / 8.0;
+const FXAA_SPAN_MAX = 8.0;
+
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let center = textureLoad(input_texture, vec2<i32>(in.position.xy), 0);
+    if post.fxaa == 0u {
+        return vec4<f32>(center.rgb, 1.0);
+    }
+
+    let texel = input_texel_size();
+    let luma_nw = sample_input(in.uv + texel * vec2<f32>(-1.0, -1.0)).a;
+    let luma_ne = sample_input(in.uv + texel * vec2<f32>(1.0, -1.0)).a;
+    let luma_sw = sample_input(in.uv + texel * vec2<f32>(-1.0, 1.0)).a;
+    let luma_se = sample_input(in.uv + texel * vec2<f32>(1.0, 1.0)).a;
+    let luma_m = center.a;
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // blur along the edge, perpendicular to the luma gradient
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN
+    );
+    let inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(
+        direction * inverse_direction_min,
+        vec2<f32>(-FXAA_SPAN_MAX),
+        vec2<f32>(FXAA_SPAN_MAX)
+    ) * texel;
+
+    let color_a = 0.5 * (
+        sample_input(in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + sample_input(in.uv + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample_input(in.uv + direction * -0.5).rgb
+        + sample_input(in.uv + direction * 0.5).rgb
+    );
+    let luma_b = sqrt(luma(color_b));
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(color_a, 1.0);
+    }
+    return vec4<f32>(color_b, 1.0);
+}
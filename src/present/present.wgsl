@@ -0,0 +1,9 @@
+#import fullscreen
+
+@group(0) @binding(0)
+var color_texture: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(color_texture, vec2<i32>(in.position.xy), 0);
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::*;
+
+/// Features the gpu timings need, requested when the adapter has them.
+pub const PROFILER_FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+
+const MAX_SCOPES: u32 = 32;
+// frames in flight before the profiler skips frames instead of stalling
+const READBACK_SLOTS: usize = 3;
+/// Samples in the rolling average of each scope.
+const TIMING_WINDOW: usize = 60;
+
+struct ReadbackSlot {
+    buffer: wgpu::Buffer,
+    // the scopes whose timestamps the buffer holds, in order
+    scopes: Vec<String>,
+    mapped: Option<Arc<AtomicBool>>,
+}
+
+struct TimestampQueries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    slots: Vec<ReadbackSlot>,
+    // the slot of the frame being encoded
+    current: Option<usize>,
+    period: f32,
+}
+
+/// Rolling averages of the time taken by each scope, in milliseconds.
+#[derive(Default)]
+pub struct Timings {
+    scopes: Vec<(String, VecDeque<f32>)>,
+}
+
+impl Timings {
+    fn add(&mut self, name: &str, milliseconds: f32) {
+        let samples = match self.scopes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, samples)) => samples,
+            None => {
+                self.scopes.push((name.to_string(), VecDeque::new()));
+                &mut self.scopes.last_mut().unwrap().1
+            }
+        };
+        if samples.len() == TIMING_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(milliseconds);
+    }
+
+    /// The name and average of each scope, in the order they first ran.
+    pub fn averages(&self) -> Vec<(String, f32)> {
+        self.scopes
+            .iter()
+            .filter(|(_, samples)| !samples.is_empty())
+            .map(|(name, samples)| {
+                let average = samples.iter().sum::<f32>() / samples.len() as f32;
+                (name.clone(), average)
+            })
+            .collect()
+    }
+}
+
+/// Times the passes of each pipeline on the gpu with timestamp queries,
+/// resolved a few frames later. Without the features it falls back to
+/// the cpu time spent encoding each pipeline.
+pub struct Profiler {
+    queries: Option<TimestampQueries>,
+    pub timings: Timings,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        if !device.features().contains(PROFILER_FEATURES) {
+            info!("no timestamp queries, profiling the cpu time of the pipelines");
+            return Self {
+                queries: None,
+                timings: Timings::default(),
+            };
+        }
+        let size = MAX_SCOPES as u64 * 2 * wgpu::QUERY_SIZE as u64;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_SCOPES * 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| ReadbackSlot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                scopes: vec![],
+                mapped: None,
+            })
+            .collect();
+        Self {
+            queries: Some(TimestampQueries {
+                query_set,
+                resolve_buffer,
+                slots,
+                current: None,
+                period: queue.get_timestamp_period(),
+            }),
+            timings: Timings::default(),
+        }
+    }
+
+    pub fn is_gpu(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Reads the frames that finished and picks a free slot for this one.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        for slot in queries.slots.iter_mut() {
+            let Some(mapped) = &slot.mapped else {
+                continue;
+            };
+            if !mapped.load(Ordering::Acquire) {
+                continue;
+            }
+            {
+                let view = slot.buffer.slice(..).get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&view);
+                for (i, name) in slot.scopes.iter().enumerate() {
+                    let (begin, end) = (timestamps[i * 2], timestamps[i * 2 + 1]);
+                    // the timestamps are garbage on some drivers when a pass was empty
+                    if end >= begin {
+                        let nanoseconds = (end - begin) as f32 * queries.period;
+                        self.timings.add(name, nanoseconds / 1_000_000.0);
+                    }
+                }
+            }
+            slot.buffer.unmap();
+            slot.scopes.clear();
+            slot.mapped = None;
+        }
+        queries.current = queries
+            .slots
+            .iter()
+            .position(|slot| slot.mapped.is_none() && slot.scopes.is_empty());
+    }
+
+    /// Times what `f` encodes, under the given name.
+    pub fn scope<R>(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        name: &str,
+        f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        let Some(queries) = &mut self.queries else {
+            let start = Instant::now();
+            let result = f(encoder);
+            self.timings
+                .add(name, start.elapsed().as_secs_f32() * 1000.0);
+            return result;
+        };
+        let Some(slot) = queries.current.map(|current| &mut queries.slots[current]) else {
+            return f(encoder);
+        };
+        let index = slot.scopes.len() as u32;
+        if index >= MAX_SCOPES {
+            return f(encoder);
+        }
+        slot.scopes.push(name.to_string());
+        encoder.write_timestamp(&queries.query_set, index * 2);
+        let result = f(encoder);
+        encoder.write_timestamp(&queries.query_set, index * 2 + 1);
+        result
+    }
+
+    /// Copies the timestamps of the frame to its slot, before submitting.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Some(slot) = queries.current.map(|current| &queries.slots[current]) else {
+            return;
+        };
+        if slot.scopes.is_empty() {
+            return;
+        }
+        let count = slot.scopes.len() as u32 * 2;
+        encoder.resolve_query_set(&queries.query_set, 0..count, &queries.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &slot.buffer,
+            0,
+            count as u64 * wgpu::QUERY_SIZE as u64,
+        );
+    }
+
+    /// Maps the slot of the frame just submitted.
+    pub fn submitted(&mut self) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Some(slot) = queries
+            .current
+            .take()
+            .map(|current| &mut queries.slots[current])
+        else {
+            return;
+        };
+        if slot.scopes.is_empty() {
+            return;
+        }
+        let mapped = Arc::new(AtomicBool::new(false));
+        let callback_mapped = mapped.clone();
+        slot.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => callback_mapped.store(true, Ordering::Release),
+                Err(err) => error!("failed to map the profiler buffer: {err}"),
+            });
+        slot.mapped = Some(mapped);
+    }
+
+    /// Waits for the frames in flight, so that the timings include them.
+    pub fn flush(&mut self, device: &wgpu::Device) {
+        device.poll(wgpu::Maintain::Wait);
+        self.begin_frame(device);
+    }
+
+    /// Logs the average time of each scope.
+    pub fn log(&self) {
+        let kind = if self.is_gpu() { "gpu" } else { "cpu" };
+        for (name, average) in self.timings.averages() {
+            info!(target: "timing", "{kind} {name}: {average:.3}ms");
+        }
+    }
+}
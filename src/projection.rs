@@ -0,0 +1,183 @@
+use std::f32::consts::PI;
+
+use glam::{Mat4, Quat, Vec3};
+
+/// How the camera looks at the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraMode {
+    /// The fly camera.
+    #[default]
+    Perspective,
+    /// Parallel rays in the direction of the fly camera.
+    Orthographic,
+    /// Parallel rays at the isometric angle, only the position moves.
+    Isometric,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [
+        CameraMode::Perspective,
+        CameraMode::Orthographic,
+        CameraMode::Isometric,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CameraMode::Perspective => "perspective",
+            CameraMode::Orthographic => "orthographic",
+            CameraMode::Isometric => "isometric",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.label() == label)
+    }
+
+    pub fn next(&self) -> Self {
+        Self::ALL[(*self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        *self != CameraMode::Perspective
+    }
+}
+
+/// Looks down at 45 degrees around the vertical axis, so that the three axes
+/// are foreshortened the same.
+pub fn isometric_rotation() -> Quat {
+    let pitch = -(1.0 / 2f32.sqrt()).atan();
+    Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, pitch)
+}
+
+/// How the camera projects the view onto the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    /// With reverse z the far plane is at infinity and this only
+    /// scales the linearized depth of the debug views.
+    pub far: f32,
+    /// Depth 1 at the near plane going to 0 at infinity, the precision of the
+    /// floats then follows the precision the perspective divide leaves.
+    pub reverse_z: bool,
+    /// The height of the view in world units when the rays are parallel,
+    /// the orthographic projection keeps its far plane with reverse z.
+    pub ortho_height: Option<f32>,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            fov_y: PI * 0.5,
+            near: 0.1,
+            far: 1000.0,
+            reverse_z: true,
+            ortho_height: None,
+        }
+    }
+}
+
+impl Projection {
+    pub fn clip_from_view(&self, aspect_ratio: f32) -> Mat4 {
+        if let Some(height) = self.ortho_height {
+            let (x, y) = (height * 0.5 * aspect_ratio, height * 0.5);
+            // swapping the planes reverses the depth
+            let (near, far) = if self.reverse_z {
+                (self.far, self.near)
+            } else {
+                (self.near, self.far)
+            };
+            return Mat4::orthographic_rh(-x, x, -y, y, near, far);
+        }
+        if self.reverse_z {
+            Mat4::perspective_infinite_reverse_rh(self.fov_y, aspect_ratio, self.near)
+        } else {
+            Mat4::perspective_rh(self.fov_y, aspect_ratio, self.near, self.far)
+        }
+    }
+
+    /// The depth buffer value where nothing was drawn.
+    pub fn far_depth(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Passes the fragments nearer than what is in the depth buffer.
+    pub fn depth_compare(&self) -> wgpu::CompareFunction {
+        if self.reverse_z {
+            wgpu::CompareFunction::Greater
+        } else {
+            wgpu::CompareFunction::Less
+        }
+    }
+
+    /// Also passes the fragments as near as what is in the depth buffer.
+    pub fn depth_compare_or_equal(&self) -> wgpu::CompareFunction {
+        if self.reverse_z {
+            wgpu::CompareFunction::GreaterEqual
+        } else {
+            wgpu::CompareFunction::LessEqual
+        }
+    }
+
+    /// Maps the view depth to 0 at the camera and 1 at the far plane, the same
+    /// curve as linearizing the depth buffer of the finite projection.
+    pub fn display_depth(&self, view_depth: f32) -> f32 {
+        (2.0 * view_depth / (view_depth + self.far)).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_conventions() {
+        let standard = Projection {
+            reverse_z: false,
+            ..Projection::default()
+        };
+        let reverse = Projection::default();
+        let depth = |projection: &Projection, distance: f32| {
+            projection
+                .clip_from_view(16.0 / 9.0)
+                .project_point3(Vec3::new(0.0, 0.0, -distance))
+                .z
+        };
+
+        assert!((depth(&standard, standard.near) - 0.0).abs() < 1e-6);
+        assert!((depth(&standard, standard.far) - standard.far_depth()).abs() < 1e-4);
+        assert!((depth(&reverse, reverse.near) - 1.0).abs() < 1e-6);
+        assert!(depth(&reverse, 1e7) < 1e-6);
+        assert!(depth(&reverse, 10.0) > depth(&reverse, 20.0));
+
+        // both show the same linearized depth, white at the far plane
+        assert_eq!(standard.display_depth(standard.far), 1.0);
+        assert_eq!(reverse.display_depth(0.0), 0.0);
+        assert!((standard.display_depth(10.0) - reverse.display_depth(10.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn orthographic() {
+        for reverse_z in [false, true] {
+            let projection = Projection {
+                reverse_z,
+                ortho_height: Some(20.0),
+                ..Projection::default()
+            };
+            let clip_from_view = projection.clip_from_view(2.0);
+            let corner = clip_from_view.project_point3(Vec3::new(20.0, 10.0, -projection.near));
+            assert!((corner.x - 1.0).abs() < 1e-6 && (corner.y - 1.0).abs() < 1e-6);
+            let far = clip_from_view.project_point3(Vec3::new(0.0, 0.0, -projection.far));
+            assert!((far.z - projection.far_depth()).abs() < 1e-6);
+            assert!((corner.z - (1.0 - projection.far_depth())).abs() < 1e-6);
+        }
+        let forward = isometric_rotation() * Vec3::NEG_Z;
+        assert!((forward.x.abs() - forward.y.abs()).abs() < 1e-6);
+        assert!((forward.y.abs() - forward.z.abs()).abs() < 1e-6);
+    }
+}
@@ -5,10 +5,12 @@ use crate::*;
 // instancing a cube a lot of times
 // this approach doesn't support transparency
 // as it would mean reordering every cube every frame to draw back to front
-const PIPELINE_NAME: &str = "Rasterize Instanced";
+pub const PIPELINE_NAME: &str = "Rasterize Instanced";
 
 const NUM_INSTANCES_PER_ROW: u32 = 32;
 
+const SHADOW_MAP_SIZE: u32 = 2048;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Instance {
@@ -43,10 +45,15 @@ impl Instance {
 struct Vertex {
     position: [f32; 3],
     uv: [f32; 2],
+    normal: [f32; 3],
 }
 impl Vertex {
-    const fn new(position: [f32; 3], uv: [f32; 2]) -> Self {
-        Self { position, uv }
+    const fn new(position: [f32; 3], uv: [f32; 2], normal: [f32; 3]) -> Self {
+        Self {
+            position,
+            uv,
+            normal,
+        }
     }
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -63,6 +70,11 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
@@ -71,58 +83,65 @@ impl Vertex {
 // flat shaded cube
 const VERTICES: &[Vertex] = &[
     // -z [0, 3, 1]
-    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0]),
-    Vertex::new([0.0, 1.0, 0.0], [0.0, 1.0]),
-    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0]),
+    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0], [0.0, 0.0, -1.0]),
+    Vertex::new([0.0, 1.0, 0.0], [0.0, 1.0], [0.0, 0.0, -1.0]),
+    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0], [0.0, 0.0, -1.0]),
     // -z [3, 2, 1]
-    Vertex::new([0.0, 1.0, 0.0], [0.0, 1.0]),
-    Vertex::new([1.0, 1.0, 0.0], [1.0, 1.0]),
-    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0]),
+    Vertex::new([0.0, 1.0, 0.0], [0.0, 1.0], [0.0, 0.0, -1.0]),
+    Vertex::new([1.0, 1.0, 0.0], [1.0, 1.0], [0.0, 0.0, -1.0]),
+    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0], [0.0, 0.0, -1.0]),
     // +y [3, 6, 2]
-    Vertex::new([0.0, 1.0, 0.0], [0.0, 0.0]),
-    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0]),
-    Vertex::new([1.0, 1.0, 0.0], [1.0, 0.0]),
+    Vertex::new([0.0, 1.0, 0.0], [0.0, 0.0], [0.0, 1.0, 0.0]),
+    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0], [0.0, 1.0, 0.0]),
+    Vertex::new([1.0, 1.0, 0.0], [1.0, 0.0], [0.0, 1.0, 0.0]),
     // +y [3, 7, 6]
-    Vertex::new([0.0, 1.0, 0.0], [0.0, 0.0]),
-    Vertex::new([0.0, 1.0, 1.0], [1.0, 0.0]),
-    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0]),
+    Vertex::new([0.0, 1.0, 0.0], [0.0, 0.0], [0.0, 1.0, 0.0]),
+    Vertex::new([0.0, 1.0, 1.0], [1.0, 0.0], [0.0, 1.0, 0.0]),
+    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0], [0.0, 1.0, 0.0]),
     // +x [1, 2, 6]
-    Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0]),
-    Vertex::new([1.0, 1.0, 0.0], [0.0, 1.0]),
-    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0]),
+    Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0], [1.0, 0.0, 0.0]),
+    Vertex::new([1.0, 1.0, 0.0], [0.0, 1.0], [1.0, 0.0, 0.0]),
+    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0], [1.0, 0.0, 0.0]),
     // +x [1, 6, 5]
-    Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0]),
-    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0]),
-    Vertex::new([1.0, 0.0, 1.0], [1.0, 0.0]),
+    Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0], [1.0, 0.0, 0.0]),
+    Vertex::new([1.0, 1.0, 1.0], [1.0, 1.0], [1.0, 0.0, 0.0]),
+    Vertex::new([1.0, 0.0, 1.0], [1.0, 0.0], [1.0, 0.0, 0.0]),
     // +z [7, 4, 6]
-    Vertex::new([0.0, 1.0, 1.0], [1.0, 1.0]),
-    Vertex::new([0.0, 0.0, 1.0], [1.0, 0.0]),
-    Vertex::new([1.0, 1.0, 1.0], [0.0, 1.0]),
+    Vertex::new([0.0, 1.0, 1.0], [1.0, 1.0], [0.0, 0.0, 1.0]),
+    Vertex::new([0.0, 0.0, 1.0], [1.0, 0.0], [0.0, 0.0, 1.0]),
+    Vertex::new([1.0, 1.0, 1.0], [0.0, 1.0], [0.0, 0.0, 1.0]),
     // +z [6, 4, 5]
-    Vertex::new([1.0, 1.0, 1.0], [0.0, 1.0]),
-    Vertex::new([0.0, 0.0, 1.0], [1.0, 0.0]),
-    Vertex::new([1.0, 0.0, 1.0], [0.0, 0.0]),
+    Vertex::new([1.0, 1.0, 1.0], [0.0, 1.0], [0.0, 0.0, 1.0]),
+    Vertex::new([0.0, 0.0, 1.0], [1.0, 0.0], [0.0, 0.0, 1.0]),
+    Vertex::new([1.0, 0.0, 1.0], [0.0, 0.0], [0.0, 0.0, 1.0]),
     // -x [7, 3, 4]
-    Vertex::new([0.0, 1.0, 1.0], [0.0, 1.0]),
-    Vertex::new([0.0, 1.0, 0.0], [1.0, 1.0]),
-    Vertex::new([0.0, 0.0, 1.0], [0.0, 0.0]),
+    Vertex::new([0.0, 1.0, 1.0], [0.0, 1.0], [-1.0, 0.0, 0.0]),
+    Vertex::new([0.0, 1.0, 0.0], [1.0, 1.0], [-1.0, 0.0, 0.0]),
+    Vertex::new([0.0, 0.0, 1.0], [0.0, 0.0], [-1.0, 0.0, 0.0]),
     // -x [4, 3, 0]
-    Vertex::new([0.0, 0.0, 1.0], [0.0, 0.0]),
-    Vertex::new([0.0, 1.0, 0.0], [1.0, 1.0]),
-    Vertex::new([0.0, 0.0, 0.0], [1.0, 0.0]),
+    Vertex::new([0.0, 0.0, 1.0], [0.0, 0.0], [-1.0, 0.0, 0.0]),
+    Vertex::new([0.0, 1.0, 0.0], [1.0, 1.0], [-1.0, 0.0, 0.0]),
+    Vertex::new([0.0, 0.0, 0.0], [1.0, 0.0], [-1.0, 0.0, 0.0]),
     // -y [5, 0, 1]
-    Vertex::new([1.0, 0.0, 1.0], [1.0, 1.0]),
-    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0]),
-    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0]),
+    Vertex::new([1.0, 0.0, 1.0], [1.0, 1.0], [0.0, -1.0, 0.0]),
+    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0], [0.0, -1.0, 0.0]),
+    Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0], [0.0, -1.0, 0.0]),
     // -y [4, 0, 5]
-    Vertex::new([0.0, 0.0, 1.0], [0.0, 1.0]),
-    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0]),
-    Vertex::new([1.0, 0.0, 1.0], [1.0, 1.0]),
+    Vertex::new([0.0, 0.0, 1.0], [0.0, 1.0], [0.0, -1.0, 0.0]),
+    Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0], [0.0, -1.0, 0.0]),
+    Vertex::new([1.0, 0.0, 1.0], [1.0, 1.0], [0.0, -1.0, 0.0]),
 ];
 
 pub struct Pipeline {
     pipeline: wgpu::RenderPipeline,
+    pipeline_gbuffer: wgpu::RenderPipeline,
+    pipeline_shadow: wgpu::RenderPipeline,
     skip: bool,
+    far_depth: f32,
+    deferred: bool,
+    //
+    shadow_map_view: wgpu::TextureView,
+    shadow_map_bind_group: BindGroupState,
     //
     vertex_buffer: wgpu::Buffer,
     instances: Vec<Instance>,
@@ -138,8 +157,9 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
         let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
@@ -148,35 +168,152 @@ impl PipelineState for Pipeline {
             panic!("diffuse bind group missing");
         };
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("rasterize_instanced.wgsl"));
+        // depth of the scene as seen from the sun
+        let shadow_map_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map Texture"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let shadow_map_view =
+            shadow_map_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let shadow_map_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let shadow_map_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Depth,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                        count: None,
+                    },
+                ],
+                label: Some("shadow_map_bind_group_layout"),
+            });
+        let shadow_map_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_map_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map_sampler),
+                },
+            ],
+            label: Some("shadow_map_bind_group"),
+        });
+        let shadow_map_bind_group = BindGroupState {
+            buffer: vec![],
+            bind_group: shadow_map_bind_group,
+            bind_group_layout: shadow_map_bind_group_layout,
+        };
+
+        let shader = include_shader!(device, "rasterize_instanced.wgsl");
         let render_pipeline_rasterize_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
                 bind_group_layouts: &[
                     &global_bind_group.bind_group_layout,
                     &diffuse_bind_group.bind_group_layout,
+                    &shadow_map_bind_group.bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
-            layout: Some(&render_pipeline_rasterize_layout),
+        let create_pipeline = |entry_point, targets: &[Option<wgpu::ColorTargetState>]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+                layout: Some(&render_pipeline_rasterize_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Vertex::desc(), Instance::desc()],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets,
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: projection.depth_compare(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0x0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline(
+            "fs_main",
+            &[Some(wgpu::ColorTargetState {
+                format: SCENE_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        );
+        let pipeline_gbuffer = create_pipeline("fs_gbuffer", &GBufferAttachment::targets());
+
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Shadow Pipeline Layout")),
+                bind_group_layouts: &[&global_bind_group.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline_shadow = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Shadow Pipeline")),
+            layout: Some(&shadow_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main",
+                entry_point: "vs_shadow",
                 buffers: &[Vertex::desc(), Instance::desc()],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
+            fragment: None,
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
@@ -191,7 +328,12 @@ impl PipelineState for Pipeline {
                 depth_write_enabled: true,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
+                // against shadow acne
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
             }),
             multisample: wgpu::MultisampleState {
                 count: 1,
@@ -226,7 +368,13 @@ impl PipelineState for Pipeline {
 
         Self {
             pipeline,
+            pipeline_gbuffer,
+            pipeline_shadow,
             skip: false,
+            far_depth: projection.far_depth(),
+            deferred: false,
+            shadow_map_view,
+            shadow_map_bind_group,
             vertex_buffer,
             instances,
             instance_buffer,
@@ -234,7 +382,16 @@ impl PipelineState for Pipeline {
         }
     }
 
-    fn extract(&mut self, sim_state: &mut SimulationState, queue: &wgpu::Queue) {
+    fn set_deferred(&mut self, deferred: bool) {
+        self.deferred = deferred;
+    }
+
+    fn extract(
+        &mut self,
+        sim_state: &mut SimulationState,
+        _frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
         let mut reload = false;
         for (world_xyz, chunk) in sim_state.universe.chunks.iter() {
             if let Some(loaded_version) = self.loaded_chunks.get_mut(world_xyz) {
@@ -275,12 +432,27 @@ impl PipelineState for Pipeline {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         clear_depth: bool,
     ) {
-        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
-            return;
+        let color_attachments = if self.deferred {
+            let Some(Attachment::GBuffer(gbuffer_attachment)) = attachments.get("gbuffer") else {
+                return;
+            };
+            gbuffer_attachment.color_attachments(clear_depth).to_vec()
+        } else {
+            let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+                return;
+            };
+            vec![Some(wgpu::RenderPassColorAttachment {
+                view: &color_attachment.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })]
         };
         let Some(Attachment::Depth(depth_attachment)) = attachments.get("depth") else {
             return;
@@ -292,21 +464,37 @@ impl PipelineState for Pipeline {
             return;
         };
 
+        {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Shadow Pass")),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            shadow_pass.set_pipeline(&self.pipeline_shadow);
+            shadow_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
+            shadow_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            shadow_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            shadow_pass.draw(0..VERTICES.len() as u32, 0..self.instances.len() as _);
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &color_attachment.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &color_attachments,
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &depth_attachment.view,
                 depth_ops: Some(wgpu::Operations {
                     load: if clear_depth {
-                        wgpu::LoadOp::Clear(1.0)
+                        wgpu::LoadOp::Clear(self.far_depth)
                     } else {
                         wgpu::LoadOp::Load
                     },
@@ -318,14 +506,23 @@ impl PipelineState for Pipeline {
             timestamp_writes: None,
         });
 
-        render_pass.set_pipeline(&self.pipeline);
+        if self.deferred {
+            render_pass.set_pipeline(&self.pipeline_gbuffer);
+        } else {
+            render_pass.set_pipeline(&self.pipeline);
+        }
         render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
         render_pass.set_bind_group(1, &diffuse_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.shadow_map_bind_group.bind_group, &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         render_pass.draw(0..VERTICES.len() as u32, 0..self.instances.len() as _);
     }
 
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Scene
+    }
+
     fn get_skip(&self) -> bool {
         self.skip
     }
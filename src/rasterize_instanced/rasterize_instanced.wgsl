@@ -1,21 +1,16 @@
-struct GlobalUniform {
-    viewport_size: vec4<f32>,
-    view_world_position: vec4<f32>,
-    world_from_clip: mat4x4<f32>,
-    clip_from_world: mat4x4<f32>,
-    view_from_clip: mat4x4<f32>,
-    clip_from_view: mat4x4<f32>,
-    view_from_world: mat4x4<f32>,
-    world_from_view: mat4x4<f32>,
-};
-@group(0) @binding(0)
-var<uniform> global: GlobalUniform;
+#import camera
+#import lighting
 
 @group(1) @binding(0)
 var diffuse_texture: texture_2d<f32>;
 @group(1) @binding(1)
 var diffuse_sampler: sampler;
 
+@group(2) @binding(0)
+var shadow_map: texture_depth_2d;
+@group(2) @binding(1)
+var shadow_sampler: sampler_comparison;
+
 struct InstanceInput {
     @location(5) pos: vec3<f32>,
     @location(6) id: u32,
@@ -24,25 +19,49 @@ struct InstanceInput {
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) uv: vec2<f32>,
+    @location(2) normal: vec3<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) id: u32,
+    @location(4) normal: vec3<f32>,
+    @location(5) world_position: vec3<f32>,
 }
 
 @vertex
 fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position = global.clip_from_world * vec4<f32>(model.position + instance.pos.xyz, 1.0);
+    let world_position = model.position + instance.pos.xyz;
+    out.clip_position = global.clip_from_world * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
     out.id = instance.id;
     out.uv = model.uv;
+    out.normal = model.normal;
     return out;
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+@vertex
+fn vs_shadow(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    return lighting.light_from_world * vec4<f32>(model.position + instance.pos.xyz, 1.0);
+}
+
+fn sun_visibility(in: VertexOutput) -> f32 {
+    // faces turned away from the sun are always in shadow
+    if dot(in.normal, lighting.light_direction.xyz) <= 0.0 {
+        return 0.0;
+    }
+    let light_clip = lighting.light_from_world * vec4<f32>(in.world_position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    let uv = uv_from_ndc(light_ndc.xy);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return 1.0;
+    }
+    return textureSampleCompareLevel(shadow_map, shadow_sampler, uv, light_ndc.z);
+}
+
+fn albedo(in: VertexOutput) -> vec4<f32> {
     let offset = vec2<f32>(
         f32(in.id % 16),
         f32(u32(in.id / 16)),
@@ -53,3 +72,22 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         (offset + in.uv) / 16.0
     );
 }
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = albedo(in);
+    let diffuse = max(dot(in.normal, lighting.light_direction.xyz), 0.0) * sun_visibility(in);
+    let light = lighting.ambient_color.rgb + lighting.light_color.rgb * diffuse;
+    return vec4<f32>(color.rgb * light, color.a);
+}
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) block_id: u32,
+}
+
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+    return GBufferOutput(albedo(in), vec4<f32>(in.normal, sun_visibility(in)), in.id);
+}
@@ -4,13 +4,17 @@ use crate::*;
 
 pub struct Pipeline {
     pipeline: wgpu::RenderPipeline,
+    pipeline_gbuffer: wgpu::RenderPipeline,
     skip: bool,
+    far_depth: f32,
+    deferred: bool,
     //
     voxels_bind_group: BindGroupState,
     loaded_chunks: HashMap<IVec3, ChunkVersion>,
+    view_mode: ViewMode,
 }
 
-const PIPELINE_NAME: &str = "Raycast Grid Plain";
+pub const PIPELINE_NAME: &str = "Raycast Grid Plain";
 
 impl PipelineState for Pipeline {
     fn get_name(&self) -> String {
@@ -19,8 +23,9 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
         let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
@@ -28,102 +33,164 @@ impl PipelineState for Pipeline {
         let Some(diffuse_bind_group) = bind_groups.get("diffuse") else {
             panic!("diffuse bind group missing");
         };
+        let Some(gi_history_bind_group) = bind_groups.get("gi_history_prev") else {
+            panic!("gi_history_prev bind group missing");
+        };
 
         let voxels_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Voxels Buffer"),
             contents: &vec![0u8; CHUNK_VOLUME * 4],
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
+        let view_mode_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("View Mode Buffer"),
+            contents: bytemuck::cast_slice(&[ViewModeUniform::new(ViewMode::default())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
         let voxels_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    VIEW_MODE_BIND_GROUP_LAYOUT[0],
+                ],
                 label: Some("voxels_bind_group_layout"),
             });
         let voxels_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &voxels_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: voxels_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: voxels_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: VIEW_MODE_BINDING,
+                    resource: view_mode_buffer.as_entire_binding(),
+                },
+            ],
             label: Some("voxels_bind_group"),
         });
         let voxels_bind_group = BindGroupState {
-            buffer: vec![voxels_buffer],
+            buffer: vec![voxels_buffer, view_mode_buffer],
             bind_group: voxels_bind_group,
             bind_group_layout: voxels_bind_group_layout,
         };
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("raycast_grid_plain.wgsl"));
+        let shader = include_shader!(device, "raycast_grid_plain.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
             bind_group_layouts: &[
                 &global_bind_group.bind_group_layout,
                 &diffuse_bind_group.bind_group_layout,
                 &voxels_bind_group.bind_group_layout,
+                &gi_history_bind_group.bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+        let create_pipeline = |entry_point, targets: &[Option<wgpu::ColorTargetState>]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets,
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: projection.depth_compare(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0x0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline(
+            "fs_main",
+            &[
+                Some(wgpu::ColorTargetState {
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0x0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+                }),
+                gi_history_target(),
+            ],
+        );
+        let pipeline_gbuffer = create_pipeline(
+            "fs_gbuffer",
+            &[
+                GBufferAttachment::targets().as_slice(),
+                &[gi_history_target()],
+            ]
+            .concat(),
+        );
 
         Self {
             pipeline,
+            pipeline_gbuffer,
             skip: false,
+            far_depth: projection.far_depth(),
+            deferred: false,
             voxels_bind_group,
             loaded_chunks: HashMap::new(),
+            view_mode: ViewMode::default(),
         }
     }
 
-    fn extract(&mut self, sim_state: &mut SimulationState, queue: &wgpu::Queue) {
+    fn set_deferred(&mut self, deferred: bool) {
+        self.deferred = deferred;
+    }
+
+    fn get_view_mode(&self) -> Option<ViewMode> {
+        Some(self.view_mode)
+    }
+
+    fn set_view_mode(&mut self, view_mode: ViewMode) {
+        self.view_mode = view_mode;
+    }
+
+    fn extract(
+        &mut self,
+        sim_state: &mut SimulationState,
+        _frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
+        queue.write_buffer(
+            &self.voxels_bind_group.buffer[1],
+            0,
+            bytemuck::cast_slice(&[ViewModeUniform::new(self.view_mode)]),
+        );
+
         let chunk_pos = IVec3::ZERO;
         let mut reload = false;
 
@@ -165,13 +232,43 @@ impl PipelineState for Pipeline {
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         clear_depth: bool,
     ) {
-        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+        let Some(Attachment::Color(gi_history_attachment)) = attachments.get("gi_history") else {
             return;
         };
+        let mut color_attachments = if self.deferred {
+            let Some(Attachment::GBuffer(gbuffer_attachment)) = attachments.get("gbuffer") else {
+                return;
+            };
+            gbuffer_attachment.color_attachments(clear_depth).to_vec()
+        } else {
+            let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+                return;
+            };
+            vec![Some(wgpu::RenderPassColorAttachment {
+                view: &color_attachment.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })]
+        };
+        color_attachments.push(Some(wgpu::RenderPassColorAttachment {
+            view: &gi_history_attachment.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: if clear_depth {
+                    wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store: wgpu::StoreOp::Store,
+            },
+        }));
         let Some(Attachment::Depth(depth_attachment)) = attachments.get("depth") else {
             return;
         };
@@ -181,22 +278,18 @@ impl PipelineState for Pipeline {
         let Some(diffuse_bind_group) = bind_groups.get("diffuse") else {
             return;
         };
+        let Some(gi_history_bind_group) = bind_groups.get("gi_history_prev") else {
+            return;
+        };
 
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &color_attachment.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &color_attachments,
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &depth_attachment.view,
                 depth_ops: Some(wgpu::Operations {
                     load: if clear_depth {
-                        wgpu::LoadOp::Clear(1.0)
+                        wgpu::LoadOp::Clear(self.far_depth)
                     } else {
                         wgpu::LoadOp::Load
                     },
@@ -208,13 +301,22 @@ impl PipelineState for Pipeline {
             timestamp_writes: None,
         });
 
-        render_pass.set_pipeline(&self.pipeline);
+        if self.deferred {
+            render_pass.set_pipeline(&self.pipeline_gbuffer);
+        } else {
+            render_pass.set_pipeline(&self.pipeline);
+        }
         render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
         render_pass.set_bind_group(1, &diffuse_bind_group.bind_group, &[]);
         render_pass.set_bind_group(2, &self.voxels_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(3, &gi_history_bind_group.bind_group, &[]);
         render_pass.draw(0..3, 0..1);
     }
 
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Scene
+    }
+
     fn get_skip(&self) -> bool {
         self.skip
     }
@@ -1,2 +1,4 @@
 pub mod pipeline;
+pub mod residency;
 pub use pipeline::*;
+pub use residency::*;
@@ -3,9 +3,14 @@ use std::sync::{Arc, RwLock};
 use glam::IVec3;
 use wgpu::ComputePassDescriptor;
 
+use super::*;
 use crate::*;
 
 const FEEDBACK_BUFFER_SIZE: usize = 16;
+/// The states of the slots of the chunks grid the raycast reads, the ones it
+/// passes through that are streamable go in the feedback.
+const CHUNK_STREAMABLE: u32 = 1;
+const CHUNK_RESIDENT: u32 = 2;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -30,17 +35,21 @@ enum FeedbackReadStatus {
 pub struct Pipeline {
     pipeline_stream: wgpu::ComputePipeline,
     pipeline_raycast: wgpu::RenderPipeline,
+    pipeline_raycast_gbuffer: wgpu::RenderPipeline,
     skip: bool,
+    far_depth: f32,
+    deferred: bool,
     //
     feedback_cpu_buffer: wgpu::Buffer,
-    feedback_gpu_bind_group: BindGroupState,
     feedback_read_available: Arc<RwLock<FeedbackReadStatus>>,
     voxels_bind_group: BindGroupState,
     //
     loaded_chunks: HashMap<IVec3, ChunkVersion>,
+    residency: ChunkResidency,
+    view_mode: ViewMode,
 }
 
-const PIPELINE_NAME: &str = "Raycast Hierarchy Feedback";
+pub const PIPELINE_NAME: &str = "Raycast Hierarchy Feedback";
 
 impl PipelineState for Pipeline {
     fn get_name(&self) -> String {
@@ -49,8 +58,9 @@ impl PipelineState for Pipeline {
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        bind_groups: &mut HashMap<String, BindGroupState>,
+        _config: &wgpu::SurfaceConfiguration,
+        projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
     ) -> Self {
         let Some(global_bind_group) = bind_groups.get("global") else {
             panic!("global bind group missing");
@@ -58,6 +68,9 @@ impl PipelineState for Pipeline {
         let Some(diffuse_bind_group) = bind_groups.get("diffuse") else {
             panic!("diffuse bind group missing");
         };
+        let Some(gi_history_bind_group) = bind_groups.get("gi_history_prev") else {
+            panic!("gi_history_prev bind group missing");
+        };
 
         let feedback = Feedback::empty();
         let feedback_gpu_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -72,51 +85,26 @@ impl PipelineState for Pipeline {
             contents: bytemuck::cast_slice(&[feedback]),
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
         });
-        let feedback_gpu_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("feedback_gpu_bind_group_layout"),
-            });
-        let feedback_gpu_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &feedback_gpu_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: feedback_gpu_buffer.as_entire_binding(),
-            }],
-            label: Some("feedback_gpu_bind_group"),
-        });
-        let feedback_gpu_bind_group = BindGroupState {
-            buffer: vec![feedback_gpu_buffer],
-            bind_group: feedback_gpu_bind_group,
-            bind_group_layout: feedback_gpu_bind_group_layout,
-        };
-
-        let chunks_grid_side = 8;
-        let chunks_grid_volume = chunks_grid_side * chunks_grid_side * chunks_grid_side;
         let chunks_grid_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Chunk Grid Buffer"),
-            contents: &vec![0u8; chunks_grid_volume],
+            contents: &vec![0u8; CHUNKS_GRID_VOLUME * 4],
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
         let voxels_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Voxels Buffer"),
-            contents: &vec![0u8; CHUNK_VOLUME * 4 * chunks_grid_volume],
-            usage: wgpu::BufferUsages::STORAGE,
+            contents: &vec![0u8; CHUNK_VOLUME * 4 * CHUNKS_GRID_VOLUME],
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
         let stream_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Stream Buffer"),
             contents: &vec![0u8; CHUNK_VOLUME * 4 * FEEDBACK_BUFFER_SIZE],
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
+        let view_mode_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("View Mode Buffer"),
+            contents: bytemuck::cast_slice(&[ViewModeUniform::new(ViewMode::default())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
         let voxels_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -150,6 +138,19 @@ impl PipelineState for Pipeline {
                         },
                         count: None,
                     },
+                    // the feedback lives in the voxels bind group to keep
+                    // the raycast pipeline within 4 bind groups
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    VIEW_MODE_BIND_GROUP_LAYOUT[0],
                 ],
                 label: Some("voxels_bind_group_layout"),
             });
@@ -168,17 +169,30 @@ impl PipelineState for Pipeline {
                     binding: 2,
                     resource: stream_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: feedback_gpu_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: VIEW_MODE_BINDING,
+                    resource: view_mode_buffer.as_entire_binding(),
+                },
             ],
             label: Some("voxels_bind_group"),
         });
         let voxels_bind_group = BindGroupState {
-            buffer: vec![chunks_grid_buffer, voxels_buffer, stream_buffer],
+            buffer: vec![
+                chunks_grid_buffer,
+                voxels_buffer,
+                stream_buffer,
+                feedback_gpu_buffer,
+                view_mode_buffer,
+            ],
             bind_group: voxels_bind_group,
             bind_group_layout: voxels_bind_group_layout,
         };
 
-        let render_shader =
-            device.create_shader_module(wgpu::include_wgsl!("raycast_hierarchy_feedback.wgsl"));
+        let render_shader = include_shader!(device, "raycast_hierarchy_feedback.wgsl");
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
@@ -186,54 +200,71 @@ impl PipelineState for Pipeline {
                     &global_bind_group.bind_group_layout,
                     &diffuse_bind_group.bind_group_layout,
                     &voxels_bind_group.bind_group_layout,
-                    &feedback_gpu_bind_group.bind_group_layout,
+                    &gi_history_bind_group.bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &render_shader,
-                entry_point: "vs_main",
-                buffers: &[],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &render_shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+        let create_render_pipeline = |entry_point, targets: &[Option<wgpu::ColorTargetState>]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &render_shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &render_shader,
+                    entry_point,
+                    targets,
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: projection.depth_compare(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0x0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let render_pipeline = create_render_pipeline(
+            "fs_main",
+            &[
+                Some(wgpu::ColorTargetState {
+                    format: SCENE_COLOR_FORMAT,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0x0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+                }),
+                gi_history_target(),
+            ],
+        );
+        let render_pipeline_gbuffer = create_render_pipeline(
+            "fs_gbuffer",
+            &[
+                GBufferAttachment::targets().as_slice(),
+                &[gi_history_target()],
+            ]
+            .concat(),
+        );
 
-        let stream_shader = device.create_shader_module(wgpu::include_wgsl!("stream_chunks.wgsl"));
+        let stream_shader = include_shader!(device, "stream_chunks.wgsl");
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&(PIPELINE_NAME.to_string() + " Stream Pipeline Layout")),
             bind_group_layouts: &[&voxels_bind_group.bind_group_layout],
@@ -249,55 +280,59 @@ impl PipelineState for Pipeline {
 
         Self {
             pipeline_raycast: render_pipeline,
+            pipeline_raycast_gbuffer: render_pipeline_gbuffer,
             pipeline_stream: stream_pipeline,
             skip: false,
+            far_depth: projection.far_depth(),
+            deferred: false,
             feedback_cpu_buffer,
-            feedback_gpu_bind_group,
             feedback_read_available: Arc::new(RwLock::new(FeedbackReadStatus::Idle)),
             voxels_bind_group,
             loaded_chunks: HashMap::new(),
+            residency: ChunkResidency::default(),
+            view_mode: ViewMode::default(),
         }
     }
 
-    fn extract(&mut self, sim_state: &mut SimulationState, queue: &wgpu::Queue) {
-        let chunk_pos = IVec3::ZERO;
-        let mut reload = false;
+    fn set_deferred(&mut self, deferred: bool) {
+        self.deferred = deferred;
+    }
 
-        let Some(chunk) = sim_state.universe.chunks.get(&chunk_pos) else {
-            warn!("no chunk at 0,0,0");
-            return;
-        };
+    fn get_view_mode(&self) -> Option<ViewMode> {
+        Some(self.view_mode)
+    }
 
-        if let Some(loaded_version) = self.loaded_chunks.get_mut(&chunk_pos) {
-            if chunk.version != *loaded_version {
-                *loaded_version = chunk.version.clone();
-                reload = true;
-            }
-        } else {
-            self.loaded_chunks
-                .insert(chunk_pos.clone(), chunk.version.clone());
-            reload = true;
-        }
+    fn set_view_mode(&mut self, view_mode: ViewMode) {
+        self.view_mode = view_mode;
+    }
 
-        if reload {
-            /*
-            let Some(chunk_data) = sim_state
-                .universe
-                .chunks
-                .get(&IVec3::ZERO)
-                .map(|c| c.get_ref())
-            else {
-                warn!("no chunk at 0,0,0");
-                return;
-            };
+    fn extract(
+        &mut self,
+        sim_state: &mut SimulationState,
+        frame: &mut FrameData,
+        queue: &wgpu::Queue,
+    ) {
+        queue.write_buffer(
+            &self.voxels_bind_group.buffer[4],
+            0,
+            bytemuck::cast_slice(&[ViewModeUniform::new(self.view_mode)]),
+        );
 
-            queue.write_buffer(
-                &self.voxels_bind_group.buffer[0],
-                0,
-                bytemuck::cast_slice(chunk_data.as_ref()),
-            );
-            */
-        }
+        self.residency.forget_evicted();
+        // the chunks that left the universe leave the pool
+        let universe = &sim_state.universe;
+        self.loaded_chunks.retain(|chunk_pos, _| {
+            let kept = universe.chunks.contains_key(chunk_pos);
+            if !kept {
+                self.residency
+                    .chunks
+                    .insert(*chunk_pos, ResidencyState::Evicted);
+            }
+            kept
+        });
+
+        self.upload_chunk(&sim_state.universe, queue);
+        self.write_chunks_grid(&sim_state.universe, queue);
 
         let status = self.feedback_read_available.read().unwrap().clone();
         match status {
@@ -320,7 +355,22 @@ impl PipelineState for Pipeline {
                 // read the mapped feedback buffer to get the request queue
                 let slice = self.feedback_cpu_buffer.slice(..).get_mapped_range();
                 let feed: &Feedback = bytemuck::from_bytes(slice.get(..).unwrap());
+                // the requests are in chunk coordinates, with a non zero w
+                let requested: Vec<IVec3> = feed
+                    .requested
+                    .iter()
+                    .filter(|request| request.w != 0.0)
+                    .map(|request| request.truncate().as_ivec3() * CHUNK_SIDE as i32)
+                    .collect();
                 drop(slice);
+                // the chunks that aren't in the universe can't be streamed
+                let universe = &sim_state.universe;
+                let loaded_chunks = &self.loaded_chunks;
+                self.residency
+                    .replace_requested(requested.into_iter().filter(|chunk_pos| {
+                        universe.chunks.contains_key(chunk_pos)
+                            && !loaded_chunks.contains_key(chunk_pos)
+                    }));
                 self.feedback_cpu_buffer.unmap();
                 *self.feedback_read_available.write().unwrap() = FeedbackReadStatus::Idle;
 
@@ -328,24 +378,162 @@ impl PipelineState for Pipeline {
 
                 // reset the gpu feedback request queue
                 queue.write_buffer(
-                    &self.feedback_gpu_bind_group.buffer[0],
+                    &self.voxels_bind_group.buffer[3],
                     0,
                     bytemuck::cast_slice(&[Feedback::empty()]),
                 );
             }
         }
+        frame.residency = Some(self.residency.clone());
     }
 
     fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        bind_groups: &HashMap<String, BindGroupState>,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        clear_depth: bool,
+    ) {
+        self.stream(encoder);
+        self.raycast(encoder, bind_groups, attachments, clear_depth);
+    }
+
+    fn render_profiled(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        clear_depth: bool,
+        profiler: &mut Profiler,
+    ) {
+        profiler.scope(
+            encoder,
+            &(PIPELINE_NAME.to_string() + " Stream"),
+            |encoder| self.stream(encoder),
+        );
+        profiler.scope(encoder, PIPELINE_NAME, |encoder| {
+            self.raycast(encoder, bind_groups, attachments, clear_depth)
+        });
+    }
+
+    fn get_stage(&self) -> PipelineStage {
+        PipelineStage::Scene
+    }
+
+    fn get_skip(&self) -> bool {
+        self.skip
+    }
+
+    fn set_skip(&mut self, skip: bool) {
+        self.skip = skip
+    }
+}
+
+impl Pipeline {
+    /// Uploads chunk 0,0,0 to its slot of the voxels pool when it's new or
+    /// changed, without it in the universe it shows as evicted.
+    fn upload_chunk(&mut self, universe: &Universe, queue: &wgpu::Queue) {
+        let chunk_pos = IVec3::ZERO;
+        let (Some(chunk), Some(slot)) = (
+            universe.chunks.get(&chunk_pos),
+            ChunkResidency::slot(chunk_pos),
+        ) else {
+            return;
+        };
+
+        // the residency follows the uploads, a chunk uploaded over an older
+        // version of itself is dirty for the frame of the upload
+        let loaded_version = self.loaded_chunks.get(&chunk_pos);
+        if loaded_version == Some(&chunk.version) {
+            self.residency
+                .chunks
+                .insert(chunk_pos, ResidencyState::Resident);
+        } else {
+            // the chunk goes in its slot of the voxels pool
+            queue.write_buffer(
+                &self.voxels_bind_group.buffer[1],
+                (slot * CHUNK_VOLUME * 4) as wgpu::BufferAddress,
+                bytemuck::cast_slice(chunk.get_ref().as_ref()),
+            );
+            let state = if loaded_version.is_some() {
+                ResidencyState::Dirty
+            } else {
+                ResidencyState::Resident
+            };
+            self.loaded_chunks.insert(chunk_pos, chunk.version.clone());
+            self.residency.chunks.insert(chunk_pos, state);
+        }
+    }
+
+    /// Marks the slots of the chunks in the universe, streamable or resident.
+    fn write_chunks_grid(&self, universe: &Universe, queue: &wgpu::Queue) {
+        let mut slots = vec![0u32; CHUNKS_GRID_VOLUME];
+        for chunk_pos in universe.chunks.keys() {
+            if let Some(slot) = ChunkResidency::slot(*chunk_pos) {
+                slots[slot] = if self.loaded_chunks.contains_key(chunk_pos) {
+                    CHUNK_RESIDENT
+                } else {
+                    CHUNK_STREAMABLE
+                };
+            }
+        }
+        queue.write_buffer(
+            &self.voxels_bind_group.buffer[0],
+            0,
+            bytemuck::cast_slice(&slots),
+        );
+    }
+
+    // the streaming of the requested chunks, a stub for now
+    fn stream(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+        let dispatch_size = 32 / 4; // chunk size / 4
+        compute_pass.set_pipeline(&self.pipeline_stream);
+        compute_pass.set_bind_group(0, &self.voxels_bind_group.bind_group, &[]);
+        compute_pass.dispatch_workgroups(dispatch_size, dispatch_size, dispatch_size);
+    }
+
+    fn raycast(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
         attachments: &HashMap<String, Attachment>,
         clear_depth: bool,
     ) {
-        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+        let Some(Attachment::Color(gi_history_attachment)) = attachments.get("gi_history") else {
             return;
         };
+        let mut color_attachments = if self.deferred {
+            let Some(Attachment::GBuffer(gbuffer_attachment)) = attachments.get("gbuffer") else {
+                return;
+            };
+            gbuffer_attachment.color_attachments(clear_depth).to_vec()
+        } else {
+            let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+                return;
+            };
+            vec![Some(wgpu::RenderPassColorAttachment {
+                view: &color_attachment.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })]
+        };
+        color_attachments.push(Some(wgpu::RenderPassColorAttachment {
+            view: &gi_history_attachment.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: if clear_depth {
+                    wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store: wgpu::StoreOp::Store,
+            },
+        }));
         let Some(Attachment::Depth(depth_attachment)) = attachments.get("depth") else {
             return;
         };
@@ -355,32 +543,19 @@ impl PipelineState for Pipeline {
         let Some(diffuse_bind_group) = bind_groups.get("diffuse") else {
             return;
         };
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
-
-            let dispatch_size = 32 / 4; // chunk size / 4
-            compute_pass.set_pipeline(&self.pipeline_stream);
-            compute_pass.set_bind_group(0, &self.voxels_bind_group.bind_group, &[]);
-            compute_pass.dispatch_workgroups(dispatch_size, dispatch_size, dispatch_size);
-        }
+        let Some(gi_history_bind_group) = bind_groups.get("gi_history_prev") else {
+            return;
+        };
 
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &color_attachment.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
+                color_attachments: &color_attachments,
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                     view: &depth_attachment.view,
                     depth_ops: Some(wgpu::Operations {
                         load: if clear_depth {
-                            wgpu::LoadOp::Clear(1.0)
+                            wgpu::LoadOp::Clear(self.far_depth)
                         } else {
                             wgpu::LoadOp::Load
                         },
@@ -392,11 +567,15 @@ impl PipelineState for Pipeline {
                 timestamp_writes: None,
             });
 
-            render_pass.set_pipeline(&self.pipeline_raycast);
+            if self.deferred {
+                render_pass.set_pipeline(&self.pipeline_raycast_gbuffer);
+            } else {
+                render_pass.set_pipeline(&self.pipeline_raycast);
+            }
             render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
             render_pass.set_bind_group(1, &diffuse_bind_group.bind_group, &[]);
             render_pass.set_bind_group(2, &self.voxels_bind_group.bind_group, &[]);
-            render_pass.set_bind_group(3, &self.feedback_gpu_bind_group.bind_group, &[]);
+            render_pass.set_bind_group(3, &gi_history_bind_group.bind_group, &[]);
             render_pass.draw(0..3, 0..1);
         }
 
@@ -405,7 +584,7 @@ impl PipelineState for Pipeline {
             FeedbackReadStatus::Idle
         ) {
             encoder.copy_buffer_to_buffer(
-                &self.feedback_gpu_bind_group.buffer[0],
+                &self.voxels_bind_group.buffer[3],
                 0,
                 &self.feedback_cpu_buffer,
                 0,
@@ -413,12 +592,4 @@ impl PipelineState for Pipeline {
             );
         }
     }
-
-    fn get_skip(&self) -> bool {
-        self.skip
-    }
-
-    fn set_skip(&mut self, skip: bool) {
-        self.skip = skip
-    }
 }
@@ -1,68 +1,26 @@
-struct GlobalUniform {
-    viewport_size: vec4<f32>,
-    view_world_position: vec4<f32>,
-    world_from_clip: mat4x4<f32>,
-    clip_from_world: mat4x4<f32>,
-    view_from_clip: mat4x4<f32>,
-    clip_from_view: mat4x4<f32>,
-    view_from_world: mat4x4<f32>,
-    world_from_view: mat4x4<f32>,
-};
-@group(0) @binding(0)
-var<uniform> global: GlobalUniform;
+#import camera
+#import fullscreen
+#import lighting
 
 @group(1) @binding(0)
 var diffuse_texture: texture_2d<f32>;
 @group(1) @binding(1)
 var diffuse_sampler: sampler;
 
+// what the pipeline knows of each slot of the chunks grid
 @group(2) @binding(0)
-var<storage, read_write> chunks_grid: array<vec4<f32>>;
+var<storage, read_write> chunks_grid: array<u32>;
 @group(2) @binding(1)
 var<storage, read_write> voxels: array<u32>;
 
-@group(3) @binding(0)
+@group(2) @binding(3)
 var<storage, read_write> feedback_request: array<vec4<f32>>;
 
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-@vertex
-fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
-    let uv = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u)) * 2.0;
-    let clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
-    return VertexOutput(clip_position, uv);
-}
-
-fn analytical_cube_ray_intersection(
-    ray_origin: vec3<f32>,
-    ray_direction: vec3<f32>,
-    min: vec3<f32>,
-    max: vec3<f32>,
-) -> vec2<f32> {
-    let v1 = (min.x - ray_origin.x) / ray_direction.x;
-    let v2 = (max.x - ray_origin.x) / ray_direction.x;
-    let v3 = (min.y - ray_origin.y) / ray_direction.y;
-    let v4 = (max.y - ray_origin.y) / ray_direction.y;
-    let v5 = (min.z - ray_origin.z) / ray_direction.z;
-    let v6 = (max.z - ray_origin.z) / ray_direction.z;
-    let v7 = max(max(min(v1, v2), min(v3, v4)), min(v5, v6));
-    let v8 = min(min(max(v1, v2), max(v3, v4)), max(v5, v6));
-    if v8 < 0.0 || v7 > v8 {
-        return vec2(0.0);
-    }
+@group(3) @binding(0)
+var gi_history_prev: texture_2d<f32>;
 
-    return vec2(v7, v8);
-}
-
-fn in_chunk_bounds(v: vec3f, offset: vec3f, size: vec3f) -> bool {
-    let x = v.x >= offset.x && v.x < offset.x + size.x;
-    let y = v.y >= offset.y && v.y < offset.y + size.y;
-    let z = v.z >= offset.z && v.z < offset.z + size.z;
-    return x && y && z;
-}
+#import voxel_lighting
+#import raycast_view
 
 fn add_to_request(pos: vec3<f32>) -> bool {
     for (var i = 0u; i < arrayLength(&feedback_request); i++) {
@@ -77,90 +35,98 @@ fn add_to_request(pos: vec3<f32>) -> bool {
     return false;
 }
 
-struct FragmentOutput {
-    @location(0) color: vec4<f32>,
-    @builtin(frag_depth) depth: f32
-}
-
-@fragment
-fn fs_main(in: VertexOutput) -> FragmentOutput {
-    // cast a ray from the camera origin that passes through the current pixel
-    var clip_space = vec2(1.0, -1.0) * (in.uv * 2.0 - 1.0);
-    let clip_far = global.world_from_clip * vec4(clip_space.x, clip_space.y, 1.0, 1.0);
-    let clip_near = global.world_from_clip * vec4(clip_space.x, clip_space.y, 0.1, 1.0);
-    let world_far = clip_far.xyz / clip_far.w;
-    let world_near = clip_near.xyz / clip_near.w;
-    let dir = normalize(world_far - world_near);
-    let dir_long = world_far - world_near;
-
-    // cube chunk intersection
-    let chunk_pos = vec3<f32>(0.0, 0.0, 0.0);
-    var ray_origin = global.view_world_position.xyz;
-    let ray_direction = dir.xyz;
-    if !in_chunk_bounds(ray_origin, chunk_pos, vec3<f32>(32.0)) {
-        let epsilon = vec3<f32>(0.00001);
-        let int = analytical_cube_ray_intersection(
-            ray_origin,
-            ray_direction,
-            chunk_pos,
-            chunk_pos + vec3<f32>(32.0)
-        );
-        if int.x <= 0.0 {
-            return FragmentOutput(vec4<f32>(0.0), 1.0);
-        } else {
-            ray_origin += ray_direction * int.x;
-        }
+// the size of the chunks grid and the slot states in raycast_hierarchy_feedback/pipeline.rs
+const CHUNKS_GRID_SIDE: u32 = 8u;
+const CHUNK_STREAMABLE: u32 = 1u;
+// the rays that hit nothing cross the whole grid
+const MISS_DISTANCE: f32 = 1e9;
+
+// walks the chunks of the grid the ray crosses before max_distance and requests
+// the ones that are in the universe but not in the pool
+fn request_missing_chunks(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) {
+    let grid_size = f32(CHUNKS_GRID_SIDE);
+    let grid = analytical_cube_ray_intersection(origin, direction, vec3<f32>(0.0), vec3<f32>(grid_size * 32.0));
+    if grid.y <= 0.0 {
+        return;
     }
-
-    // raycast inside the chunk using DDA (digital differential analyzer)
-    var side = -1.0;
-    var voxel_id = 0u;
-    var map = floor(ray_origin);
-    let delta_dist = 1.0 / abs(ray_direction);
-    let s = step(vec3<f32>(0.0), ray_direction);
+    // in chunk units from where the ray enters the grid
+    let entry = max(grid.x, 0.0);
+    let start = (origin + direction * entry) / 32.0;
+    var map = clamp(floor(start), vec3<f32>(0.0), vec3<f32>(grid_size - 1.0));
+    let delta_dist = 1.0 / abs(direction);
+    let s = step(vec3<f32>(0.0), direction);
     let step_dir = 2.0 * s - 1.0;
-    var side_dist = (s - step_dir * fract(ray_origin)) * delta_dist;
-    var i = 0u;
-    for (; i < 50u; i++) {
-        let conds = step(side_dist.xxyy, side_dist.yzzx);
-        var cases = vec3<f32>(0.0);
-        cases.x = conds.x * conds.y;
-        cases.y = (1. - cases.x) * conds.z * conds.w;
-        cases.z = (1. - cases.x) * (1. - cases.y);
-        side_dist += max((2.0 * cases - 1.0) * delta_dist, vec3<f32>(0.0));
-        map += cases * step_dir;
-        if !in_chunk_bounds(map, chunk_pos, vec3<f32>(32.0)) {
-            break;
+    var side_dist = (s - step_dir * (start - map)) * delta_dist;
+    var distance = entry;
+    for (var i = 0u; i < CHUNKS_GRID_SIDE * 3u; i++) {
+        if distance > max_distance || any(map < vec3<f32>(0.0)) || any(map >= vec3<f32>(grid_size)) {
+            return;
         }
-
-        let idx = u32(map.x) * (32u * 32u) + u32(map.y) * 32u + u32(map.z);
-        voxel_id = voxels[idx];
-        if voxel_id > 0 {
-            side = cases.y + 2. * cases.z;
-            break;
+        let slot = u32(map.x) + (u32(map.y) + u32(map.z) * CHUNKS_GRID_SIDE) * CHUNKS_GRID_SIDE;
+        if chunks_grid[slot] == CHUNK_STREAMABLE {
+            add_to_request(map);
         }
+        let next = min(side_dist.x, min(side_dist.y, side_dist.z));
+        let cases = select(vec3<f32>(0.0), vec3<f32>(1.0), side_dist == vec3<f32>(next));
+        distance = entry + next * 32.0;
+        side_dist += cases * delta_dist;
+        map += cases * step_dir;
     }
+}
 
-    if side == -1 {
-        // no hit
-        return FragmentOutput(vec4<f32>(0.0), 1.0);
-    }
+struct Hit {
+    color: vec4<f32>,
+    normal: vec3<f32>,
+    voxel_id: u32,
+    depth: f32,
+    sun_visibility: f32,
+    indirect: vec3<f32>,
+    linear_depth: f32,
+}
+
+fn no_hit() -> Hit {
+    return Hit(vec4<f32>(0.0), vec3<f32>(0.0), 0u, far_depth(), 0.0, vec3<f32>(0.0), 0.0);
+}
+
+fn raycast(screen_uv: vec2<f32>) -> Hit {
+    // cast a ray from the camera origin, or the near plane of the parallel
+    // views, that passes through the current pixel
+    var ray_origin = ray_origin_from_uv(screen_uv);
+    let ray_direction = ray_direction_from_uv(screen_uv);
 
-    // calculate the normal for ambient occlusion
-    var normal = -vec3<f32>(f32(side == 0), f32(side == 1), f32(side == 2)) * step_dir;
+    // cube chunk intersection
+    let entry = chunk_entry_distance(ray_origin, ray_direction);
+    if entry < 0.0 {
+        request_missing_chunks(ray_origin, ray_direction, MISS_DISTANCE);
+        return no_hit();
+    }
+    let camera_origin = ray_origin;
+    ray_origin += ray_direction * entry;
+
+    // raycast inside the chunk using DDA
+    let voxel = trace_ray(ray_origin, ray_direction, view.max_steps);
+    if voxel.voxel_id == 0u {
+        request_missing_chunks(camera_origin, ray_direction, MISS_DISTANCE);
+        // the walks that miss cost steps too, they show in front of the sky
+        var miss = no_hit();
+        if view.view_mode == VIEW_STEPS {
+            miss.color = step_color(voxel.steps);
+            miss.depth = miss_depth();
+        }
+        return miss;
+    }
+    let voxel_id = voxel.voxel_id;
+    let normal = voxel.normal;
+    let hit = voxel.position;
 
-    // find intersection point by intersecting with the face's plane
-    var n = vec3<f32>(f32(side == 0), f32(side == 1), f32(side == 2));
-    let p = map + 0.5 - step_dir * 0.5;
-    let t = (dot(n, p - ray_origin)) / dot(n, ray_direction);
-    let hit = ray_origin + ray_direction * t;
-    let uvw = hit - map;
+    // the texture coordinates lie on the face that was hit
+    let uvw = hit - voxel.map;
     var uv = vec2<f32>(0.0);
-    if side == 0 {
+    if normal.x != 0.0 {
         uv = uvw.yz;
-    } else if side == 1 {
+    } else if normal.y != 0.0 {
         uv = uvw.zx;
-    } else if side == 2 {
+    } else {
         uv = uvw.xy;
     }
 
@@ -175,10 +141,76 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
         (offset + uv) / 16.0
     );
 
-    feedback_request[0] = vec4<f32>(1.0, 3.0, 1.0, 1.0);
-    feedback_request[1] = vec4<f32>(2.0, 1.0, 1.0, 2.0);
+    request_missing_chunks(camera_origin, ray_direction, length(hit - camera_origin));
 
     let clip = global.clip_from_world * vec4<f32>(hit, 1.0);
-    let depth = max(0.1, clip.z / clip.w);
-    return FragmentOutput(color, depth);
+    let depth = depth_from_clip(clip);
+    let linear_depth = linear_depth_from_clip(clip);
+
+    // the debug views skip the lighting rays
+    if view.view_mode != VIEW_TEXTURED {
+        let distance = length(hit - ray_origin_from_uv(screen_uv));
+        let view_color = view_color(voxel_id, normal, distance, linear_depth, voxel.steps);
+        return Hit(view_color, normal, voxel_id, depth, 1.0, lighting.ambient_color.rgb, linear_depth);
+    }
+
+    // secondary ray, faces turned away from the sun are always in shadow
+    var sun_visibility = 0.0;
+    if dot(normal, lighting.light_direction.xyz) > 0.0 {
+        sun_visibility = trace_shadow(hit + normal * 0.001, lighting.light_direction.xyz);
+    }
+
+    // without gi rays fall back to a constant ambient
+    var indirect = lighting.ambient_color.rgb;
+    if lighting.gi_ray_count > 0u {
+        let pixel = vec2<u32>(screen_uv * global.viewport_size.xy);
+        var seed = hash(pixel.x + hash(pixel.y + hash(global.frame_index)));
+        indirect = accumulate_indirect(hit, trace_indirect(hit, normal, &seed));
+    }
+
+    return Hit(color, normal, voxel_id, depth, sun_visibility, indirect, linear_depth);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) gi_history: vec4<f32>,
+    @builtin(frag_depth) depth: f32
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let hit = raycast(in.uv);
+    var color = shade(hit.color, hit.normal, hit.sun_visibility, hit.indirect);
+    if view.view_mode != VIEW_TEXTURED {
+        color = hit.color;
+    }
+    return FragmentOutput(
+        color,
+        vec4<f32>(hit.indirect, hit.linear_depth),
+        hit.depth
+    );
+}
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) block_id: u32,
+    @location(3) gi_history: vec4<f32>,
+    @builtin(frag_depth) depth: f32
+}
+
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+    let hit = raycast(in.uv);
+    var block_id = hit.voxel_id;
+    if view.view_mode != VIEW_TEXTURED {
+        block_id = UNLIT_BLOCK_ID;
+    }
+    return GBufferOutput(
+        hit.color,
+        vec4<f32>(hit.normal, hit.sun_visibility),
+        block_id,
+        vec4<f32>(hit.indirect, hit.linear_depth),
+        hit.depth
+    );
 }
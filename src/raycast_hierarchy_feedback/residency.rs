@@ -0,0 +1,115 @@
+use glam::IVec3;
+
+use crate::*;
+
+/// Chunks along each side of the chunks grid, the pool of the chunks on the gpu.
+pub const CHUNKS_GRID_SIDE: usize = 8;
+pub const CHUNKS_GRID_VOLUME: usize = CHUNKS_GRID_SIDE * CHUNKS_GRID_SIDE * CHUNKS_GRID_SIDE;
+
+/// Where a chunk is in the streaming loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResidencyState {
+    /// In the pool and up to date.
+    Resident = 1,
+    /// Asked for by the feedback of the raycast and not streamed yet.
+    Requested = 2,
+    /// Was in the pool and left it.
+    Evicted = 3,
+    /// In the pool but changed, uploaded again this frame.
+    Dirty = 4,
+}
+
+/// The chunks the pipeline knows of, by their position in the world.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkResidency {
+    pub chunks: HashMap<IVec3, ResidencyState>,
+}
+
+impl ChunkResidency {
+    /// The slot of the chunks grid a chunk maps to, the grid starts at the origin.
+    pub fn slot(chunk_pos: IVec3) -> Option<usize> {
+        let grid_pos = chunk_pos.div_euclid(IVec3::splat(CHUNK_SIDE as i32));
+        let side = CHUNKS_GRID_SIDE as i32;
+        if grid_pos.cmplt(IVec3::ZERO).any() || grid_pos.cmpge(IVec3::splat(side)).any() {
+            return None;
+        }
+        Some((grid_pos.x + grid_pos.y * side + grid_pos.z * side * side) as usize)
+    }
+
+    /// The state of each slot of the chunks grid.
+    pub fn slots(&self) -> Vec<Option<ResidencyState>> {
+        let mut slots = vec![None; CHUNKS_GRID_VOLUME];
+        for (chunk_pos, state) in &self.chunks {
+            if let Some(slot) = Self::slot(*chunk_pos) {
+                slots[slot] = Some(*state);
+            }
+        }
+        slots
+    }
+
+    /// Forgets the chunks evicted by the previous extract, they show for one frame.
+    pub fn forget_evicted(&mut self) {
+        self.chunks
+            .retain(|_, state| *state != ResidencyState::Evicted);
+    }
+
+    /// Replaces the chunks of the previous feedback with the ones it requests now.
+    pub fn replace_requested(&mut self, requested: impl IntoIterator<Item = IVec3>) {
+        self.chunks
+            .retain(|_, state| *state != ResidencyState::Requested);
+        self.chunks.extend(
+            requested
+                .into_iter()
+                .map(|chunk_pos| (chunk_pos, ResidencyState::Requested)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots() {
+        let side = CHUNK_SIDE as i32;
+        assert_eq!(ChunkResidency::slot(IVec3::ZERO), Some(0));
+        assert_eq!(ChunkResidency::slot(IVec3::new(side, 0, 0)), Some(1));
+        assert_eq!(
+            ChunkResidency::slot(IVec3::new(0, side, side * 2)),
+            Some(CHUNKS_GRID_SIDE + CHUNKS_GRID_SIDE * CHUNKS_GRID_SIDE * 2)
+        );
+        assert_eq!(ChunkResidency::slot(IVec3::new(-side, 0, 0)), None);
+        assert_eq!(ChunkResidency::slot(IVec3::splat(side * 8)), None);
+
+        let mut residency = ChunkResidency::default();
+        residency
+            .chunks
+            .insert(IVec3::new(side, 0, 0), ResidencyState::Requested);
+        let slots = residency.slots();
+        assert_eq!(slots[1], Some(ResidencyState::Requested));
+        assert_eq!(slots.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn stale_states_are_dropped() {
+        let side = CHUNK_SIDE as i32;
+        let mut residency = ChunkResidency::default();
+        residency.chunks.extend([
+            (IVec3::ZERO, ResidencyState::Resident),
+            (IVec3::new(side, 0, 0), ResidencyState::Evicted),
+        ]);
+        residency.replace_requested([IVec3::new(0, side, 0)]);
+        residency.replace_requested([IVec3::new(0, 0, side)]);
+        residency.forget_evicted();
+        let mut chunks: Vec<_> = residency.chunks.into_iter().collect();
+        chunks.sort_by_key(|(chunk_pos, _)| chunk_pos.to_array());
+        assert_eq!(
+            chunks,
+            [
+                (IVec3::ZERO, ResidencyState::Resident),
+                (IVec3::new(0, 0, side), ResidencyState::Requested),
+            ]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 @group(0) @binding(0)
-var<storage, read_write> chunks_grid: array<vec4<f32>>;
+var<storage, read_write> chunks_grid: array<u32>;
 @group(0) @binding(1)
 var<storage, read_write> voxels: array<u32>;
 @group(0) @binding(2)
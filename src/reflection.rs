@@ -0,0 +1,211 @@
+/// The layout of a `#[repr(C)]` struct that the shaders declare as well.
+pub struct HostStruct {
+    pub name: &'static str,
+    pub size: usize,
+    /// The name and offset of each field, padding fields start with `_`.
+    pub fields: Vec<(&'static str, usize)>,
+    /// The layout of the bind group the struct is bound in, and its binding.
+    pub layout: &'static [wgpu::BindGroupLayoutEntry],
+    pub binding: u32,
+}
+
+/// Builds the [`HostStruct`] of a type from the list of its fields.
+#[macro_export]
+macro_rules! host_struct {
+    ($ty:ident { $($field:ident),* $(,)? }, $layout:expr, $binding:expr) => {
+        $crate::reflection::HostStruct {
+            name: stringify!($ty),
+            size: std::mem::size_of::<$ty>(),
+            fields: vec![$((stringify!($field), std::mem::offset_of!($ty, $field))),*],
+            layout: $layout,
+            binding: $binding,
+        }
+    };
+}
+
+/// Compares the structs of a shader that have the name of a host struct
+/// and the variables bound with them, returns every mismatch.
+pub fn check_module(module: &naga::Module, hosts: &[HostStruct]) -> Vec<String> {
+    let mut errors = vec![];
+    let find_host = |ty: &naga::Type| {
+        let name = ty.name.as_deref()?;
+        hosts.iter().find(|host| host.name == name)
+    };
+
+    for (_, ty) in module.types.iter() {
+        let naga::TypeInner::Struct { members, span } = &ty.inner else {
+            continue;
+        };
+        let Some(host) = find_host(ty) else {
+            continue;
+        };
+        let name = host.name;
+        if *span as usize != host.size {
+            errors.push(format!(
+                "{name} is {span} bytes in wgsl and {} in rust",
+                host.size
+            ));
+        }
+        for member in members {
+            let member_name = member.name.as_deref().unwrap_or_default();
+            match host.fields.iter().find(|(field, _)| *field == member_name) {
+                Some((_, offset)) if *offset != member.offset as usize => errors.push(format!(
+                    "{name}.{member_name} is at offset {} in wgsl and {offset} in rust",
+                    member.offset
+                )),
+                Some(_) => {}
+                None => errors.push(format!("{name}.{member_name} is missing in rust")),
+            }
+        }
+        for (field, _) in &host.fields {
+            let in_wgsl = members.iter().any(|m| m.name.as_deref() == Some(field));
+            if !in_wgsl && !field.starts_with('_') {
+                errors.push(format!("{name}.{field} is missing in wgsl"));
+            }
+        }
+    }
+
+    for (_, variable) in module.global_variables.iter() {
+        let Some(host) = find_host(&module.types[variable.ty]) else {
+            continue;
+        };
+        let Some(binding) = &variable.binding else {
+            continue;
+        };
+        let name = variable.name.as_deref().unwrap_or_default();
+        if variable.space != naga::AddressSpace::Uniform {
+            errors.push(format!("{name}: {} is not bound as a uniform", host.name));
+        }
+        if binding.binding != host.binding {
+            errors.push(format!(
+                "{name}: {} is at binding {} in wgsl and {} in rust",
+                host.name, binding.binding, host.binding
+            ));
+        }
+        let entry = host.layout.iter().find(|e| e.binding == host.binding);
+        match entry.map(|entry| entry.ty) {
+            Some(wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                min_binding_size,
+                ..
+            }) => {
+                if min_binding_size.map(|size| size.get() as usize) != Some(host.size) {
+                    errors.push(format!(
+                        "the layout entry of {} has min_binding_size {min_binding_size:?}, \
+                        the struct is {} bytes",
+                        host.name, host.size
+                    ));
+                }
+            }
+            _ => errors.push(format!(
+                "the layout of {} has no uniform buffer at binding {}",
+                host.name, host.binding
+            )),
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::*;
+
+    fn parse(source: &str) -> naga::Module {
+        naga::front::wgsl::parse_str(source).unwrap()
+    }
+
+    // every shader is checked without a gpu, the modules in src/shaders
+    // are checked through the shaders that import them
+    #[test]
+    fn shaders_match_host_structs() {
+        let hosts = host_structs();
+        let modules_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders");
+        let mut errors = vec![];
+        let mut directories = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("src")];
+        while let Some(directory) = directories.pop() {
+            for entry in fs::read_dir(&directory).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    if path != modules_directory {
+                        directories.push(path);
+                    }
+                    continue;
+                }
+                if path.extension().is_none_or(|extension| extension != "wgsl") {
+                    continue;
+                }
+                let source = fs::read_to_string(&path).unwrap();
+                let shader = match preprocess(&path, &source, load_module) {
+                    Ok(shader) => shader,
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    }
+                };
+                let module = match naga::front::wgsl::parse_str(&shader.source) {
+                    Ok(module) => module,
+                    Err(err) => {
+                        errors.push(format!("{path:?}: {}", err.message()));
+                        continue;
+                    }
+                };
+                for err in check_module(&module, &hosts) {
+                    errors.push(format!("{path:?}: {err}"));
+                }
+            }
+        }
+        assert!(errors.is_empty(), "{}", errors.join("\n"));
+    }
+
+    #[repr(C)]
+    struct Example {
+        a: f32,
+        b: glam::Vec4,
+    }
+
+    const EXAMPLE_LAYOUT: &[wgpu::BindGroupLayoutEntry] = &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Example>() as u64),
+        },
+        count: None,
+    }];
+
+    #[test]
+    fn detects_mismatches() {
+        let hosts = [host_struct!(Example { a, b }, EXAMPLE_LAYOUT, 0)];
+        let matching = parse(
+            "struct Example { a: f32, b: vec4<f32> };
+            @group(0) @binding(0) var<uniform> example: Example;",
+        );
+        assert_eq!(check_module(&matching, &hosts), Vec::<String>::new());
+
+        let reordered = parse(
+            "struct Example { b: vec4<f32>, a: f32 };
+            @group(0) @binding(0) var<uniform> example: Example;",
+        );
+        let errors = check_module(&reordered, &hosts);
+        assert!(errors
+            .iter()
+            .any(|err| err.contains("Example.b is at offset 0")));
+
+        let missing = parse(
+            "struct Example { a: f32, c: f32, b: vec4<f32> };
+            @group(0) @binding(1) var<uniform> example: Example;",
+        );
+        let errors = check_module(&missing, &hosts);
+        assert!(errors
+            .iter()
+            .any(|err| err.contains("Example.c is missing in rust")));
+        assert!(errors
+            .iter()
+            .any(|err| err.contains("at binding 1 in wgsl")));
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+use crate::*;
+
+/// When this environment variable is set the shaders are read from disk
+/// instead of the copies embedded at compile time, and are reloaded when they change.
+pub const HOT_RELOAD_ENV: &str = "SHADER_HOT_RELOAD";
+
+pub fn hot_reload_enabled() -> bool {
+    std::env::var_os(HOT_RELOAD_ENV).is_some()
+}
+
+/// Creates a shader module from a wgsl file next to the calling source file,
+/// like `wgpu::include_wgsl!` but reloadable.
+#[macro_export]
+macro_rules! include_shader {
+    ($device:expr, $path:literal) => {
+        $crate::shaders::create_shader_module($device, file!(), $path, include_str!($path))
+    };
+}
+
+/// Shared modules that a shader can pull in with `#import <name>`,
+/// read from `src/shaders/<name>.wgsl` when hot reloading.
+const SHADER_MODULES: &[(&str, &str)] = &[
+    ("camera", include_str!("shaders/camera.wgsl")),
+    ("fullscreen", include_str!("shaders/fullscreen.wgsl")),
+    ("intersect", include_str!("shaders/intersect.wgsl")),
+    ("lighting", include_str!("shaders/lighting.wgsl")),
+    ("random", include_str!("shaders/random.wgsl")),
+    ("raycast_view", include_str!("shaders/raycast_view.wgsl")),
+    ("voxel_dda", include_str!("shaders/voxel_dda.wgsl")),
+    (
+        "voxel_lighting",
+        include_str!("shaders/voxel_lighting.wgsl"),
+    ),
+];
+
+fn module_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/shaders")
+        .join(name)
+        .with_extension("wgsl")
+}
+
+/// The files and the errors of the shaders created during a recording.
+#[derive(Default)]
+pub struct ShaderRecord {
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+thread_local! {
+    static RECORD: RefCell<Option<ShaderRecord>> = const { RefCell::new(None) };
+}
+
+/// Runs f and returns the shader files it loaded, used to know which
+/// pipelines to rebuild when a file changes, and the errors found in them.
+pub fn record_shaders<T>(f: impl FnOnce() -> T) -> (T, ShaderRecord) {
+    RECORD.with(|record| *record.borrow_mut() = Some(ShaderRecord::default()));
+    let result = f();
+    let record = RECORD.with(|record| record.borrow_mut().take());
+    (result, record.unwrap_or_default())
+}
+
+fn with_record(f: impl FnOnce(&mut ShaderRecord)) {
+    RECORD.with(|record| {
+        if let Some(record) = record.borrow_mut().as_mut() {
+            f(record);
+        }
+    });
+}
+
+fn read_source(path: &Path, embedded: &str) -> String {
+    if !hot_reload_enabled() {
+        return embedded.to_string();
+    }
+    match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            warn!("can't read {path:?}, using the embedded shader: {err}");
+            embedded.to_string()
+        }
+    }
+}
+
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    caller: &str,
+    path: &str,
+    embedded: &str,
+) -> wgpu::ShaderModule {
+    let full_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(caller)
+        .with_file_name(path);
+    let source = read_source(&full_path, embedded);
+    let source = match preprocess(&full_path, &source, load_module) {
+        Ok(shader) => {
+            with_record(|record| record.files.extend(shader.files.iter().cloned()));
+            // the uniforms are checked against the rust structs here
+            // since wgpu would only notice a different size
+            let errors = match naga::front::wgsl::parse_str(&shader.source) {
+                Ok(module) => check_module(&module, &host_structs())
+                    .into_iter()
+                    .map(|err| format!("{}: {err}", display_path(&full_path)))
+                    .collect(),
+                Err(err) => {
+                    // naga points at the line of the preprocessed source,
+                    // report where that line was written instead
+                    let location = err
+                        .location(&shader.source)
+                        .and_then(|location| shader.origin(location.line_number as usize));
+                    vec![match location {
+                        Some(location) => format!("{location}: {}", err.message()),
+                        None => format!("{}: {}", display_path(&full_path), err.message()),
+                    }]
+                }
+            };
+            for err in errors {
+                error!("{err}");
+                with_record(|record| record.errors.push(err));
+            }
+            shader.source
+        }
+        Err(err) => {
+            // the source can't be built, let wgpu fail on it like on any invalid shader
+            error!("{err}");
+            with_record(|record| {
+                record.files.push(full_path.clone());
+                record.errors.push(err);
+            });
+            source
+        }
+    };
+
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(path),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// Returns the path and source of a shared module from its name.
+pub fn load_module(name: &str) -> Result<(PathBuf, String), String> {
+    let Some((_, embedded)) = SHADER_MODULES.iter().find(|(n, _)| *n == name) else {
+        return Err(format!("unknown shader module {name}"));
+    };
+    let path = module_path(name);
+    let source = read_source(&path, embedded);
+    Ok((path, source))
+}
+
+fn display_path(path: &Path) -> String {
+    path.strip_prefix(env!("CARGO_MANIFEST_DIR"))
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Where a line of a preprocessed shader was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", display_path(&self.file), self.line)
+    }
+}
+
+pub struct PreprocessedShader {
+    pub source: String,
+    /// The origin of every line of the source.
+    pub lines: Vec<SourceLocation>,
+    /// The shader and the modules it imported.
+    pub files: Vec<PathBuf>,
+}
+
+impl PreprocessedShader {
+    /// The origin of a line of the source, counted from 1.
+    pub fn origin(&self, line: usize) -> Option<&SourceLocation> {
+        self.lines.get(line.checked_sub(1)?)
+    }
+}
+
+/// Resolves the directives of a wgsl source:
+/// - `#import <name>` pastes a module, only the first time it's imported
+/// - `#define <NAME> [value]` replaces the identifier NAME with value from then on
+/// - `#ifdef <NAME>`, `#ifndef <NAME>`, `#else`, `#endif` keep the lines
+///   in between depending on NAME being defined
+///
+/// load_module returns the path and source of a module from its name.
+pub fn preprocess(
+    path: &Path,
+    source: &str,
+    load_module: impl Fn(&str) -> Result<(PathBuf, String), String>,
+) -> Result<PreprocessedShader, String> {
+    let mut preprocessor = Preprocessor {
+        load_module: &load_module,
+        defines: HashMap::new(),
+        imported: vec![],
+        conditions: vec![],
+        shader: PreprocessedShader {
+            source: String::new(),
+            lines: vec![],
+            files: vec![],
+        },
+    };
+    preprocessor.process(path, source)?;
+    Ok(preprocessor.shader)
+}
+
+type ModuleLoader<'a> = &'a dyn Fn(&str) -> Result<(PathBuf, String), String>;
+
+struct Preprocessor<'a> {
+    load_module: ModuleLoader<'a>,
+    defines: HashMap<String, String>,
+    imported: Vec<String>,
+    // for each open #ifdef, whether its lines are kept
+    conditions: Vec<bool>,
+    shader: PreprocessedShader,
+}
+
+impl Preprocessor<'_> {
+    fn active(&self) -> bool {
+        self.conditions.iter().all(|active| *active)
+    }
+
+    fn process(&mut self, path: &Path, source: &str) -> Result<(), String> {
+        self.shader.files.push(path.to_path_buf());
+        let open_conditions = self.conditions.len();
+        for (i, line) in source.lines().enumerate() {
+            let location = SourceLocation {
+                file: path.to_path_buf(),
+                line: i + 1,
+            };
+            let Some(directive) = line.trim().strip_prefix('#') else {
+                if self.active() {
+                    self.shader.source += &self.substitute(line);
+                    self.shader.source.push('\n');
+                    self.shader.lines.push(location);
+                }
+                continue;
+            };
+
+            let mut words = directive.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            let name = words.next();
+            let value = words.collect::<Vec<_>>().join(" ");
+            let name = || name.ok_or_else(|| format!("{location}: #{keyword} needs a name"));
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains_key(name()?);
+                    self.conditions.push(defined == (keyword == "ifdef"));
+                }
+                "else" | "endif" if self.conditions.len() <= open_conditions => {
+                    return Err(format!("{location}: #{keyword} without #ifdef"));
+                }
+                "else" => {
+                    let last = self.conditions.len() - 1;
+                    self.conditions[last] = !self.conditions[last];
+                }
+                "endif" => {
+                    self.conditions.pop();
+                }
+                _ if !self.active() => {}
+                "define" => {
+                    self.defines.insert(name()?.to_string(), value);
+                }
+                "import" => {
+                    let name = name()?;
+                    if self.imported.iter().any(|imported| imported == name) {
+                        continue;
+                    }
+                    self.imported.push(name.to_string());
+                    let (module_path, module_source) =
+                        (self.load_module)(name).map_err(|err| format!("{location}: {err}"))?;
+                    self.process(&module_path, &module_source)?;
+                }
+                _ => return Err(format!("{location}: unknown directive #{keyword}")),
+            }
+        }
+        if self.conditions.len() > open_conditions {
+            return Err(format!("{}: #ifdef without #endif", display_path(path)));
+        }
+        Ok(())
+    }
+
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.is_empty() {
+            return line.to_string();
+        }
+        let mut result = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c.is_alphanumeric() || c == '_') {
+            result += &rest[..start];
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            result += self.defines.get(word).map_or(word, |value| value.as_str());
+            rest = &rest[end..];
+        }
+        result + rest
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Polls the modification time of every wgsl file in the source directory.
+pub struct ShaderWatcher {
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        let mut watcher = Self {
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.scan();
+        watcher
+    }
+
+    /// Returns the files that changed since the last call.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+        let mut directories = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("src")];
+        while let Some(directory) = directories.pop() {
+            let Ok(entries) = fs::read_dir(&directory) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|extension| extension != "wgsl") {
+                    continue;
+                }
+                let Ok(modified) = entry.metadata().and_then(|m| m.modified()) else {
+                    continue;
+                };
+                if self.modified.insert(path.clone(), modified) != Some(modified) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modules(name: &str) -> Result<(PathBuf, String), String> {
+        let source = match name {
+            "common" => "const PI: f32 = 3.14159;",
+            "lighting" => "#import common\nfn shade() -> f32 {\n    return PI;\n}",
+            "broken" => "fn ok() {}\nfn broken( {}",
+            _ => return Err(format!("unknown shader module {name}")),
+        };
+        Ok((PathBuf::from(format!("{name}.wgsl")), source.to_string()))
+    }
+
+    fn run(source: &str) -> Result<PreprocessedShader, String> {
+        preprocess(Path::new("main.wgsl"), source, modules)
+    }
+
+    #[test]
+    fn imports_once() {
+        let shader = run("#import common\n#import lighting\n#import common").unwrap();
+        assert_eq!(shader.source.matches("const PI").count(), 1);
+        assert_eq!(
+            shader.files,
+            ["main.wgsl", "common.wgsl", "lighting.wgsl"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "\
+#define OUTER
+#ifdef OUTER
+a
+#ifndef INNER
+b
+#else
+c
+#endif
+#ifdef INNER
+d
+#endif
+#else
+e
+#endif";
+        let shader = run(source).unwrap();
+        assert_eq!(shader.source, "a\nb\n");
+    }
+
+    #[test]
+    fn defines() {
+        let shader =
+            run("#define SIZE 8\nvar<private> a: array<f32, SIZE>;\nlet SIZE_2 = SIZE;").unwrap();
+        assert_eq!(
+            shader.source,
+            "var<private> a: array<f32, 8>;\nlet SIZE_2 = 8;\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            run("a\n#ifdef A\nb").err().unwrap(),
+            "main.wgsl: #ifdef without #endif"
+        );
+        assert_eq!(
+            run("a\n#import missing").err().unwrap(),
+            "main.wgsl:2: unknown shader module missing"
+        );
+        assert_eq!(
+            run("#endif").err().unwrap(),
+            "main.wgsl:1: #endif without #ifdef"
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_written_line() {
+        let shader = run("#import lighting\n\nfn main() {}\n#import broken").unwrap();
+        let err = naga::front::wgsl::parse_str(&shader.source).unwrap_err();
+        let line = err.location(&shader.source).unwrap().line_number as usize;
+        let origin = shader.origin(line).unwrap();
+        assert_eq!(origin.to_string(), "broken.wgsl:2");
+        // after the line of common and the three of lighting
+        assert_eq!(shader.origin(5).unwrap().to_string(), "main.wgsl:2");
+    }
+}
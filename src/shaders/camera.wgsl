@@ -0,0 +1,98 @@
+// the per frame camera, bound by every pipeline that draws the scene
+struct GlobalUniform {
+    viewport_size: vec4<f32>,
+    view_world_position: vec4<f32>,
+    world_from_clip: mat4x4<f32>,
+    clip_from_world: mat4x4<f32>,
+    view_from_clip: mat4x4<f32>,
+    clip_from_view: mat4x4<f32>,
+    view_from_world: mat4x4<f32>,
+    world_from_view: mat4x4<f32>,
+    prev_clip_from_world: mat4x4<f32>,
+    frame_index: u32,
+    time: f32,
+    // the rays are parallel and start on the near plane
+    orthographic: u32,
+    jitter: vec4<f32>,
+    // the far plane is at infinity with reverse z
+    near: f32,
+    far: f32,
+    fov_y: f32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> global: GlobalUniform;
+
+// the screen uv points down, ndc points up
+fn ndc_from_uv(uv: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+fn uv_from_ndc(ndc: vec2<f32>) -> vec2<f32> {
+    return ndc * vec2<f32>(0.5, -0.5) + 0.5;
+}
+
+fn world_from_ndc(ndc: vec3<f32>) -> vec3<f32> {
+    let world = global.world_from_clip * vec4<f32>(ndc, 1.0);
+    return world.xyz / world.w;
+}
+
+// the depth buffer value at the near plane, and where nothing was drawn
+fn near_depth() -> f32 {
+    return select(0.0, 1.0, global.reverse_z != 0u);
+}
+
+fn far_depth() -> f32 {
+    return select(1.0, 0.0, global.reverse_z != 0u);
+}
+
+fn is_far_depth(depth: f32) -> bool {
+    return depth == far_depth();
+}
+
+// the depth to write for a point, the ones in front of the near plane stay on it
+fn depth_from_clip(clip: vec4<f32>) -> f32 {
+    return clamp(clip.z / clip.w, 0.0, 1.0);
+}
+
+// moves the infinite far plane of reverse z to a distance the matrices can invert
+fn finite_depth(depth: f32) -> f32 {
+    if global.reverse_z != 0u {
+        return max(depth, 1e-6);
+    }
+    return depth;
+}
+
+// the distance along the view direction of a clip position, the orthographic
+// depth is linear between the planes
+fn linear_depth_from_clip(clip: vec4<f32>) -> f32 {
+    if global.orthographic == 0u {
+        return clip.w;
+    }
+    let depth = clip.z / clip.w;
+    return select(mix(global.near, global.far, depth), mix(global.far, global.near, depth), global.reverse_z != 0u);
+}
+
+// the distance along the view direction of a depth buffer value
+fn view_depth(ndc: vec2<f32>, depth: f32) -> f32 {
+    let view = global.view_from_clip * vec4<f32>(ndc, finite_depth(depth), 1.0);
+    return -view.z / view.w;
+}
+
+// where the ray through the pixel at uv starts, the parallel rays start on the near plane
+fn ray_origin_from_uv(uv: vec2<f32>) -> vec3<f32> {
+    if global.orthographic != 0u {
+        return world_from_ndc(vec3<f32>(ndc_from_uv(uv), near_depth()));
+    }
+    return global.view_world_position.xyz;
+}
+
+// direction of the ray from the camera origin that passes through the pixel at uv,
+// the point of the near plane in view space is already relative to the camera
+fn ray_direction_from_uv(uv: vec2<f32>) -> vec3<f32> {
+    if global.orthographic != 0u {
+        return normalize((global.world_from_view * vec4<f32>(0.0, 0.0, -1.0, 0.0)).xyz);
+    }
+    let view = global.view_from_clip * vec4<f32>(ndc_from_uv(uv), near_depth(), 1.0);
+    return normalize((global.world_from_view * vec4<f32>(view.xyz / view.w, 0.0)).xyz);
+}
@@ -0,0 +1,21 @@
+// returns the intersections between a ray and a cuboid (axis aligned, not rotated)
+fn analytical_cube_ray_intersection(
+    ray_origin: vec3<f32>,
+    ray_direction: vec3<f32>,
+    min: vec3<f32>,
+    max: vec3<f32>,
+) -> vec2<f32> {
+    let v1 = (min.x - ray_origin.x) / ray_direction.x;
+    let v2 = (max.x - ray_origin.x) / ray_direction.x;
+    let v3 = (min.y - ray_origin.y) / ray_direction.y;
+    let v4 = (max.y - ray_origin.y) / ray_direction.y;
+    let v5 = (min.z - ray_origin.z) / ray_direction.z;
+    let v6 = (max.z - ray_origin.z) / ray_direction.z;
+    let v7 = max(max(min(v1, v2), min(v3, v4)), min(v5, v6));
+    let v8 = min(min(max(v1, v2), max(v3, v4)), max(v5, v6));
+    if v8 < 0.0 || v7 > v8 {
+        return vec2(0.0);
+    }
+
+    return vec2(v7, v8);
+}
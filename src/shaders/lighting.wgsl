@@ -0,0 +1,13 @@
+struct LightingUniform {
+    sun_direction: vec4<f32>,
+    moon_direction: vec4<f32>,
+    // the sun during the day and the moon at night
+    light_direction: vec4<f32>,
+    light_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    light_from_world: mat4x4<f32>,
+    gi_ray_count: u32,
+    fog_density: f32,
+};
+@group(0) @binding(1)
+var<uniform> lighting: LightingUniform;
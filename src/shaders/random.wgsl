@@ -0,0 +1,23 @@
+// pcg hash
+fn hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+// cosine weighted direction in the hemisphere around the normal
+fn random_hemisphere(normal: vec3<f32>, seed: ptr<function, u32>) -> vec3<f32> {
+    let z = random(seed) * 2.0 - 1.0;
+    let a = random(seed) * 6.2831853;
+    let r = sqrt(1.0 - z * z);
+    let direction = normal + vec3<f32>(r * cos(a), r * sin(a), z);
+    if dot(direction, direction) < 0.0001 {
+        return normal;
+    }
+    return normalize(direction);
+}
@@ -0,0 +1,76 @@
+// the debug views of the voxel raycasters, the importer owns group 2 and
+// binds the mode of the pipeline next to its voxels, see view_mode.rs
+#import camera
+#import random
+
+const VIEW_TEXTURED: u32 = 0u;
+const VIEW_STEPS: u32 = 1u;
+const VIEW_NORMALS: u32 = 2u;
+const VIEW_DEPTH: u32 = 3u;
+const VIEW_BLOCK_ID: u32 = 4u;
+const VIEW_DISTANCE: u32 = 5u;
+
+// the block id written to the gbuffer by the debug views, deferred lighting
+// passes their colors through unlit
+const UNLIT_BLOCK_ID: u32 = 0xffffffffu;
+
+struct ViewModeUniform {
+    view_mode: u32,
+    max_steps: u32,
+    depth_range: f32,
+    distance_band: f32,
+};
+@group(2) @binding(4)
+var<uniform> view: ViewModeUniform;
+
+// the depth of the misses in the step view, in front of the far plane
+// so that the depth test keeps them
+fn miss_depth() -> f32 {
+    return mix(far_depth(), near_depth(), 1e-5);
+}
+
+// black through blue, green and yellow to red
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    if x < 1.0 {
+        return mix(vec3<f32>(0.0), vec3<f32>(0.0, 0.0, 1.0), x);
+    } else if x < 2.0 {
+        return mix(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0), x - 1.0);
+    } else if x < 3.0 {
+        return mix(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 1.0, 0.0), x - 2.0);
+    }
+    return mix(vec3<f32>(1.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), x - 3.0);
+}
+
+fn step_color(steps: u32) -> vec4<f32> {
+    return vec4<f32>(heatmap(f32(steps) / f32(max(view.max_steps, 1u))), 1.0);
+}
+
+// the color of a hit in the debug views, distance is along the ray from where it
+// started and linear_depth along the view direction
+fn view_color(voxel_id: u32, normal: vec3<f32>, distance: f32, linear_depth: f32, steps: u32) -> vec4<f32> {
+    switch view.view_mode {
+        case VIEW_STEPS: {
+            return step_color(steps);
+        }
+        case VIEW_NORMALS: {
+            return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+        }
+        case VIEW_DEPTH: {
+            return vec4<f32>(vec3<f32>(clamp(linear_depth / view.depth_range, 0.0, 1.0)), 1.0);
+        }
+        case VIEW_BLOCK_ID: {
+            let h = hash(voxel_id);
+            return vec4<f32>(vec3<f32>(vec3<u32>(h, h >> 8u, h >> 16u) & vec3<u32>(255u)) / 255.0, 1.0);
+        }
+        case VIEW_DISTANCE: {
+            // alternating bands along a ramp of eight of them, so that small differences show
+            let band = floor(distance / view.distance_band);
+            let shade = select(0.75, 1.0, band % 2.0 == 0.0);
+            return vec4<f32>(heatmap(distance / (view.distance_band * 8.0)) * shade, 1.0);
+        }
+        default: {
+            return vec4<f32>(0.0);
+        }
+    }
+}
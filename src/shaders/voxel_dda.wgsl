@@ -0,0 +1,77 @@
+// walks a 32^3 chunk stored in a `voxels: array<u32>` declared by the importer,
+// the name of the array can be changed with `#define VOXELS <name>`
+#import intersect
+
+#ifndef VOXELS
+#define VOXELS voxels
+#endif
+
+fn in_chunk_bounds(v: vec3f, offset: vec3f, size: vec3f) -> bool {
+    let x = v.x >= offset.x && v.x < offset.x + size.x;
+    let y = v.y >= offset.y && v.y < offset.y + size.y;
+    let z = v.z >= offset.z && v.z < offset.z + size.z;
+    return x && y && z;
+}
+
+// distance along the ray to where it enters the chunk,
+// zero when it starts inside and negative when it misses
+fn chunk_entry_distance(origin: vec3<f32>, direction: vec3<f32>) -> f32 {
+    let chunk_pos = vec3<f32>(0.0, 0.0, 0.0);
+    if in_chunk_bounds(origin, chunk_pos, vec3<f32>(32.0)) {
+        return 0.0;
+    }
+    let int = analytical_cube_ray_intersection(
+        origin,
+        direction,
+        chunk_pos,
+        chunk_pos + vec3<f32>(32.0)
+    );
+    if int.x <= 0.0 {
+        return -1.0;
+    }
+    return int.x;
+}
+
+struct VoxelHit {
+    voxel_id: u32,
+    normal: vec3<f32>,
+    position: vec3<f32>,
+    // the voxel that was hit
+    map: vec3<f32>,
+    // iterations of the walk, for misses as well
+    steps: u32,
+}
+
+// walk the chunk with DDA (digital differential analyzer) from origin,
+// voxel_id is 0 when nothing is hit
+fn trace_ray(origin: vec3<f32>, direction: vec3<f32>, max_steps: u32) -> VoxelHit {
+    let chunk_pos = vec3<f32>(0.0, 0.0, 0.0);
+    var map = floor(origin);
+    let delta_dist = 1.0 / abs(direction);
+    let s = step(vec3<f32>(0.0), direction);
+    let step_dir = 2.0 * s - 1.0;
+    var side_dist = (s - step_dir * fract(origin)) * delta_dist;
+    var i = 0u;
+    for (; i < max_steps; i++) {
+        let conds = step(side_dist.xxyy, side_dist.yzzx);
+        var cases = vec3<f32>(0.0);
+        cases.x = conds.x * conds.y;
+        cases.y = (1. - cases.x) * conds.z * conds.w;
+        cases.z = (1. - cases.x) * (1. - cases.y);
+        side_dist += max((2.0 * cases - 1.0) * delta_dist, vec3<f32>(0.0));
+        map += cases * step_dir;
+        if !in_chunk_bounds(map, chunk_pos, vec3<f32>(32.0)) {
+            break;
+        }
+
+        let idx = u32(map.x) * (32u * 32u) + u32(map.y) * 32u + u32(map.z);
+        let voxel_id = VOXELS[idx];
+        if voxel_id > 0 {
+            // intersect with the plane of the face the ray entered from
+            let p = map + 0.5 - step_dir * 0.5;
+            let t = dot(cases, p - origin) / dot(cases, direction);
+            return VoxelHit(voxel_id, -cases * step_dir, origin + direction * t, map, i + 1u);
+        }
+    }
+    return VoxelHit(0u, vec3<f32>(0.0), vec3<f32>(0.0), vec3<f32>(0.0), min(i + 1u, max_steps));
+}
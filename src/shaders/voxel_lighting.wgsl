@@ -0,0 +1,78 @@
+// sun shadows and one bounce of diffuse gi for the voxel raycasters, the importer
+// declares the block atlas in `diffuse_texture` and `diffuse_sampler` and the
+// accumulated gi of the previous frame in `gi_history_prev`
+#import camera
+#import lighting
+#import random
+#import voxel_dda
+
+// march from a surface towards the sun, any voxel along the way casts a shadow
+fn trace_shadow(origin: vec3<f32>, direction: vec3<f32>) -> f32 {
+    return f32(trace_ray(origin, direction, 100u).voxel_id == 0u);
+}
+
+fn sun_light(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    // faces turned away from the sun are always in shadow
+    let diffuse = dot(normal, lighting.light_direction.xyz);
+    if diffuse <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let sun_visibility = trace_shadow(position + normal * 0.001, lighting.light_direction.xyz);
+    return lighting.light_color.rgb * diffuse * sun_visibility;
+}
+
+// the color of a block is approximated by the center of its texture tile
+fn block_albedo(voxel_id: u32) -> vec3<f32> {
+    let offset = vec2<f32>(f32(voxel_id % 16), f32(u32(voxel_id / 16)));
+    return textureSampleLevel(
+        diffuse_texture,
+        diffuse_sampler,
+        (offset + 0.5) / 16.0,
+        0.0
+    ).rgb;
+}
+
+const GI_RAY_STEPS = 24u;
+
+// one bounce of diffuse light, the rays that escape see the ambient sky
+// so the occluded ones also give ambient occlusion
+fn trace_indirect(position: vec3<f32>, normal: vec3<f32>, seed: ptr<function, u32>) -> vec3<f32> {
+    var indirect = vec3<f32>(0.0);
+    for (var i = 0u; i < lighting.gi_ray_count; i++) {
+        let direction = random_hemisphere(normal, seed);
+        let bounce = trace_ray(position + normal * 0.001, direction, GI_RAY_STEPS);
+        if bounce.voxel_id == 0u {
+            indirect += lighting.ambient_color.rgb;
+        } else {
+            indirect += block_albedo(bounce.voxel_id) * sun_light(bounce.position, bounce.normal);
+        }
+    }
+    return indirect / f32(lighting.gi_ray_count);
+}
+
+const GI_HISTORY_BLEND = 0.1;
+
+// blend with what the same surface point accumulated in the previous frames
+fn accumulate_indirect(position: vec3<f32>, indirect: vec3<f32>) -> vec3<f32> {
+    let prev_clip = global.prev_clip_from_world * vec4<f32>(position, 1.0);
+    if prev_clip.w <= 0.0 {
+        return indirect;
+    }
+    let prev_uv = uv_from_ndc(prev_clip.xy / prev_clip.w);
+    if any(prev_uv < vec2<f32>(0.0)) || any(prev_uv >= vec2<f32>(1.0)) {
+        return indirect;
+    }
+    let prev = textureLoad(gi_history_prev, vec2<i32>(prev_uv * global.viewport_size.xy), 0);
+    // the history stores the linear depth it was accumulated at, a mismatch means disocclusion
+    let prev_depth = linear_depth_from_clip(prev_clip);
+    if abs(prev.w - prev_depth) > prev_depth * 0.05 {
+        return indirect;
+    }
+    return mix(prev.rgb, indirect, GI_HISTORY_BLEND);
+}
+
+fn shade(albedo: vec4<f32>, normal: vec3<f32>, sun_visibility: f32, indirect: vec3<f32>) -> vec4<f32> {
+    let diffuse = max(dot(normal, lighting.light_direction.xyz), 0.0) * sun_visibility;
+    let light = indirect + lighting.light_color.rgb * diffuse;
+    return vec4<f32>(albedo.rgb * light, albedo.a);
+}
@@ -0,0 +1,118 @@
+#import camera
+#import lighting
+#import fullscreen
+
+@group(1) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var depth_sampler: sampler;
+
+const PI = 3.14159265;
+
+// earth-like atmosphere in meters, the camera stands on the ground
+const PLANET_RADIUS = 6371e3;
+const ATMOSPHERE_RADIUS = 6471e3;
+const RAYLEIGH_SCATTERING = vec3<f32>(5.5e-6, 13.0e-6, 22.4e-6);
+const RAYLEIGH_SCALE_HEIGHT = 8e3;
+const MIE_SCATTERING = 21e-6;
+const MIE_SCALE_HEIGHT = 1.2e3;
+const MIE_G = 0.758;
+const SUN_INTENSITY = 22.0;
+const NIGHT_SKY_COLOR = vec3<f32>(0.002, 0.003, 0.008);
+const MOON_COLOR = vec3<f32>(0.8, 0.85, 1.0);
+const PRIMARY_STEPS = 16;
+const SECONDARY_STEPS = 8;
+
+// distances along the ray to the two intersections with a sphere at the origin,
+// the second is smaller than the first when the ray misses
+fn ray_sphere(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b = dot(origin, direction);
+    let c = dot(origin, origin) - radius * radius;
+    let d = b * b - c;
+    if d < 0.0 {
+        return vec2<f32>(1e5, -1e5);
+    }
+    let s = sqrt(d);
+    return vec2<f32>(-b - s, -b + s);
+}
+
+// single scattering of sunlight by the rayleigh and mie particles along the view ray
+fn atmosphere(direction: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    let origin = vec3<f32>(0.0, PLANET_RADIUS + 100.0, 0.0);
+    var distance = ray_sphere(origin, direction, ATMOSPHERE_RADIUS).y;
+    let ground = ray_sphere(origin, direction, PLANET_RADIUS);
+    if ground.x > 0.0 {
+        distance = min(distance, ground.x);
+    }
+    let step_size = distance / f32(PRIMARY_STEPS);
+
+    let mu = dot(direction, sun);
+    let phase_rayleigh = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    let g2 = MIE_G * MIE_G;
+    let phase_mie = 3.0 / (8.0 * PI) * ((1.0 - g2) * (1.0 + mu * mu))
+        / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * mu * MIE_G, 1.5));
+
+    var total_rayleigh = vec3<f32>(0.0);
+    var total_mie = vec3<f32>(0.0);
+    var optical_depth_rayleigh = 0.0;
+    var optical_depth_mie = 0.0;
+    for (var i = 0; i < PRIMARY_STEPS; i++) {
+        let position = origin + direction * (f32(i) + 0.5) * step_size;
+        let height = length(position) - PLANET_RADIUS;
+        let density_rayleigh = exp(-height / RAYLEIGH_SCALE_HEIGHT) * step_size;
+        let density_mie = exp(-height / MIE_SCALE_HEIGHT) * step_size;
+        optical_depth_rayleigh += density_rayleigh;
+        optical_depth_mie += density_mie;
+
+        // the planet shadows the points the sun has set on
+        if ray_sphere(position, sun, PLANET_RADIUS).x > 0.0 {
+            continue;
+        }
+        let sun_step_size = ray_sphere(position, sun, ATMOSPHERE_RADIUS).y / f32(SECONDARY_STEPS);
+        var sun_optical_depth_rayleigh = 0.0;
+        var sun_optical_depth_mie = 0.0;
+        for (var j = 0; j < SECONDARY_STEPS; j++) {
+            let sun_position = position + sun * (f32(j) + 0.5) * sun_step_size;
+            let sun_height = length(sun_position) - PLANET_RADIUS;
+            sun_optical_depth_rayleigh += exp(-sun_height / RAYLEIGH_SCALE_HEIGHT) * sun_step_size;
+            sun_optical_depth_mie += exp(-sun_height / MIE_SCALE_HEIGHT) * sun_step_size;
+        }
+
+        let attenuation = exp(-(
+            MIE_SCATTERING * (optical_depth_mie + sun_optical_depth_mie)
+            + RAYLEIGH_SCATTERING * (optical_depth_rayleigh + sun_optical_depth_rayleigh)
+        ));
+        total_rayleigh += density_rayleigh * attenuation;
+        total_mie += density_mie * attenuation;
+    }
+
+    return SUN_INTENSITY * (
+        phase_rayleigh * RAYLEIGH_SCATTERING * total_rayleigh
+        + phase_mie * MIE_SCATTERING * total_mie
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ndc = ndc_from_uv(in.uv);
+    let direction = ray_direction_from_uv(in.uv);
+    let sun = lighting.sun_direction.xyz;
+    let moon = lighting.moon_direction.xyz;
+    // the faint night sky is what remains once the sun has set
+    let sky = atmosphere(direction, sun) + NIGHT_SKY_COLOR;
+
+    let depth = textureLoad(depth_texture, vec2<i32>(in.position.xy), 0).x;
+    if is_far_depth(depth) {
+        // nothing was drawn here, show the sky and the disks above the horizon
+        let sun_disk = smoothstep(0.9995, 0.9998, dot(direction, sun)) * step(0.0, sun.y);
+        let moon_disk = smoothstep(0.9994, 0.9997, dot(direction, moon)) * step(0.0, moon.y);
+        return vec4<f32>(sky + sun_disk * SUN_INTENSITY + moon_disk * MOON_COLOR, 1.0);
+    }
+
+    // fade the geometry towards the sky color behind it, the parallel views
+    // fade from the near plane
+    let position = world_from_ndc(vec3<f32>(ndc, depth));
+    let distance = length(position - ray_origin_from_uv(in.uv));
+    let fog = 1.0 - exp(-distance * lighting.fog_density);
+    return vec4<f32>(sky, fog);
+}
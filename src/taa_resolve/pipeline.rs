@@ -0,0 +1,145 @@
+use crate::*;
+
+// blends the jittered scene color with the reprojected history of the previous frames
+pub struct Pipeline {
+    pipeline: wgpu::RenderPipeline,
+    skip: bool,
+}
+
+pub const PIPELINE_NAME: &str = "TAA Resolve";
+
+impl PipelineState for Pipeline {
+    fn get_name(&self) -> String {
+        PIPELINE_NAME.to_string()
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        _projection: &Projection,
+        bind_groups: &mut HashMap<String, Rc<BindGroupState>>,
+    ) -> Self {
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            panic!("global bind group missing");
+        };
+        let Some(color_bind_group) = bind_groups.get("color") else {
+            panic!("color bind group missing");
+        };
+        let Some(depth_bind_group) = bind_groups.get("depth") else {
+            panic!("depth bind group missing");
+        };
+        let Some(taa_history_bind_group) = bind_groups.get("taa_history_prev") else {
+            panic!("taa_history_prev bind group missing");
+        };
+
+        let shader = include_shader!(device, "taa_resolve.wgsl");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline Layout")),
+            bind_group_layouts: &[
+                &global_bind_group.bind_group_layout,
+                &color_bind_group.bind_group_layout,
+                &depth_bind_group.bind_group_layout,
+                &taa_history_bind_group.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&(PIPELINE_NAME.to_string() + " Render Pipeline")),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_COLOR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            skip: false,
+        }
+    }
+
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &HashMap<String, Rc<BindGroupState>>,
+        attachments: &HashMap<String, Attachment>,
+        _clear_depth: bool,
+    ) {
+        let Some(Attachment::Color(color_attachment)) = attachments.get("color") else {
+            return;
+        };
+        let Some(Attachment::Color(taa_history_attachment)) = attachments.get("taa_history") else {
+            return;
+        };
+        let (Some(color_texture), Some(taa_history_texture)) =
+            (&color_attachment.texture, &taa_history_attachment.texture)
+        else {
+            return;
+        };
+        let Some(global_bind_group) = bind_groups.get("global") else {
+            return;
+        };
+        let Some(color_bind_group) = bind_groups.get("color") else {
+            return;
+        };
+        let Some(depth_bind_group) = bind_groups.get("depth") else {
+            return;
+        };
+        let Some(taa_history_bind_group) = bind_groups.get("taa_history_prev") else {
+            return;
+        };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&(PIPELINE_NAME.to_string() + " Render Pass")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &taa_history_attachment.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &global_bind_group.bind_group, &[]);
+            render_pass.set_bind_group(1, &color_bind_group.bind_group, &[]);
+            render_pass.set_bind_group(2, &depth_bind_group.bind_group, &[]);
+            render_pass.set_bind_group(3, &taa_history_bind_group.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        // the resolved image replaces the scene color, the pipelines after
+        // this one draw on top of it without ending up in the history
+        encoder.copy_texture_to_texture(
+            taa_history_texture.as_image_copy(),
+            color_texture.as_image_copy(),
+            color_texture.size(),
+        );
+    }
+
+    fn get_skip(&self) -> bool {
+        self.skip
+    }
+
+    fn set_skip(&mut self, skip: bool) {
+        self.skip = skip
+    }
+}
@@ -0,0 +1,66 @@
+#import camera
+#import fullscreen
+
+@group(1) @binding(0)
+var color_texture: texture_2d<f32>;
+
+@group(2) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var depth_sampler: sampler;
+
+@group(3) @binding(0)
+var history_texture: texture_2d<f32>;
+
+const HISTORY_BLEND = 0.1;
+
+fn load_history(texel: vec2<i32>) -> vec4<f32> {
+    let max_texel = vec2<i32>(global.viewport_size.xy) - 1;
+    return textureLoad(history_texture, clamp(texel, vec2<i32>(0), max_texel), 0);
+}
+
+// the history is read with textureLoad, filter it by hand
+fn sample_history(uv: vec2<f32>) -> vec4<f32> {
+    let position = uv * global.viewport_size.xy - 0.5;
+    let base = vec2<i32>(floor(position));
+    let f = fract(position);
+    let top = mix(load_history(base), load_history(base + vec2<i32>(1, 0)), f.x);
+    let bottom = mix(load_history(base + vec2<i32>(0, 1)), load_history(base + vec2<i32>(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let max_texel = vec2<i32>(global.viewport_size.xy) - 1;
+    let current = textureLoad(color_texture, texel, 0);
+
+    // the history is clamped to the colors around the pixel, anything outside
+    // of them was disoccluded or changed and would otherwise ghost
+    var color_min = current;
+    var color_max = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbor_texel = clamp(texel + vec2<i32>(x, y), vec2<i32>(0), max_texel);
+            let neighbor = textureLoad(color_texture, neighbor_texel, 0);
+            color_min = min(color_min, neighbor);
+            color_max = max(color_max, neighbor);
+        }
+    }
+
+    // motion comes from the depth: find the world position of the pixel
+    // and where the previous frame projected it
+    let depth = textureLoad(depth_texture, texel, 0).x;
+    let world = world_from_ndc(vec3<f32>(ndc_from_uv(in.uv), finite_depth(depth)));
+    let prev_clip = global.prev_clip_from_world * vec4<f32>(world, 1.0);
+    if prev_clip.w <= 0.0 {
+        return current;
+    }
+    let prev_uv = uv_from_ndc(prev_clip.xy / prev_clip.w);
+    if any(prev_uv < vec2<f32>(0.0)) || any(prev_uv >= vec2<f32>(1.0)) {
+        return current;
+    }
+
+    let history = clamp(sample_history(prev_uv), color_min, color_max);
+    return mix(history, current, HISTORY_BLEND);
+}
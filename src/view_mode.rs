@@ -0,0 +1,99 @@
+/// What the voxel raycasters output instead of the shaded scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum ViewMode {
+    #[default]
+    Textured = 0,
+    /// DDA iterations of the primary ray, misses included.
+    Steps = 1,
+    Normals = 2,
+    /// The view space depth of the hit.
+    Depth = 3,
+    /// A color per block id.
+    BlockId = 4,
+    /// The distance from the camera to the hit, in bands of a few voxels.
+    Distance = 5,
+}
+
+impl ViewMode {
+    pub const ALL: [ViewMode; 6] = [
+        ViewMode::Textured,
+        ViewMode::Steps,
+        ViewMode::Normals,
+        ViewMode::Depth,
+        ViewMode::BlockId,
+        ViewMode::Distance,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ViewMode::Textured => "textured",
+            ViewMode::Steps => "steps",
+            ViewMode::Normals => "normals",
+            ViewMode::Depth => "depth",
+            ViewMode::BlockId => "block id",
+            ViewMode::Distance => "distance",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.label() == label)
+    }
+
+    pub fn next(&self) -> Self {
+        Self::ALL[(*self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Bound next to the voxels of each raycaster, they are out of bind groups.
+pub const VIEW_MODE_BINDING: u32 = 4;
+/// DDA steps of the primary rays, the hottest color of the heatmap.
+pub const PRIMARY_MAX_STEPS: u32 = 50;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ViewModeUniform {
+    pub view_mode: u32,
+    pub max_steps: u32,
+    // the view depth shown white
+    pub depth_range: f32,
+    // the length of the bands of the distance view
+    pub distance_band: f32,
+}
+
+impl ViewModeUniform {
+    pub fn new(view_mode: ViewMode) -> Self {
+        Self {
+            view_mode: view_mode as u32,
+            max_steps: PRIMARY_MAX_STEPS,
+            depth_range: 64.0,
+            distance_band: 8.0,
+        }
+    }
+}
+
+pub const VIEW_MODE_BIND_GROUP_LAYOUT: &[wgpu::BindGroupLayoutEntry] =
+    &[wgpu::BindGroupLayoutEntry {
+        binding: VIEW_MODE_BINDING,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ViewModeUniform>() as u64),
+        },
+        count: None,
+    }];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_round_trip() {
+        for mode in ViewMode::ALL {
+            assert_eq!(ViewMode::from_label(mode.label()), Some(mode));
+        }
+        assert_eq!(ViewMode::Distance.next(), ViewMode::Textured);
+        assert_eq!(ViewMode::from_label("heat"), None);
+    }
+}
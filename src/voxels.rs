@@ -59,6 +59,14 @@ impl Universe {
             .map(|chunk| chunk.read_block(inner_pos))
     }
 
+    /// Returns the min and max corners of the box containing every chunk.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        let chunk_size = IVec3::splat(CHUNK_SIDE as i32);
+        let min = self.chunks.keys().copied().reduce(IVec3::min)?;
+        let max = self.chunks.keys().copied().reduce(IVec3::max)?;
+        Some((min, max + chunk_size))
+    }
+
     pub fn set_chunk_block(&mut self, pos: &IVec3, block: Block) {
         let (chunk_pos, inner_pos) = self.pos_to_chunk_and_inner(pos);
         if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {